@@ -0,0 +1,132 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Drive a rover across mars surface without falling into a crater!
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Read the configuration from this file instead of the XDG config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Override a single option, e.g. `--set color_rover=Red` or `--set levels.0.points=50`
+    #[arg(long, global = true, value_name = "KEY=VALUE", value_parser = parse_set)]
+    pub set: Vec<(String, String)>,
+
+    // the scoreboard used to be printed with `-s`, which still works
+    #[arg(short = 's', hide = true)]
+    pub scores: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub play: PlayArgs,
+}
+
+impl Cli {
+    // the options of the game can be given with or without `play`, but not
+    // both ways at once; afterwards `play` is the same as no subcommand
+    pub fn parse_args() -> Self {
+        let mut cli = Cli::parse();
+        match cli.command.take() {
+            Some(Command::Play(args)) => {
+                if cli.play.given() {
+                    error(
+                        ErrorKind::ArgumentConflict,
+                        "the options of the game have to come after `play`",
+                    );
+                }
+                cli.play = args;
+            }
+            Some(command) => {
+                if cli.play.given() {
+                    error(
+                        ErrorKind::ArgumentConflict,
+                        "the options of the game can only be used with `play`",
+                    );
+                }
+                cli.command = Some(command);
+            }
+            None => (),
+        }
+        cli
+    }
+}
+
+fn parse_set(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err("expected KEY=VALUE".to_string()),
+    }
+}
+
+// reports a problem with the arguments the way clap does and exits
+pub fn error(kind: ErrorKind, message: &str) -> ! {
+    Cli::command().error(kind, message).exit()
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Play the game, this is the default
+    Play(PlayArgs),
+    /// Print the scoreboard
+    Scores,
+    /// Print the configuration in use
+    Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Check the configuration file and report every problem in it
+    Check,
+    /// Print the default configuration with a comment on every option
+    Default {
+        /// Write it to the configuration file instead, if there is none yet
+        #[arg(long)]
+        write: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct PlayArgs {
+    /// Generate the world from this seed, to play the exact same world again
+    #[arg(long, conflicts_with = "replay")]
+    pub seed: Option<u64>,
+
+    /// Start at this level; such a run does not end up on the scoreboard
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "replay")]
+    pub level: usize,
+
+    /// Play the levels of the level pack with this name
+    #[arg(long, value_name = "NAME", conflicts_with = "replay")]
+    pub pack: Option<String>,
+
+    /// Write every action of the run to a replay file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Play back the actions of a recorded run
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+}
+
+impl PlayArgs {
+    fn given(&self) -> bool {
+        self.seed.is_some()
+            || self.level > 0
+            || self.pack.is_some()
+            || self.record.is_some()
+            || self.replay.is_some()
+    }
+}
@@ -5,7 +5,14 @@
 extern crate xdg;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::keys::Keys;
+use crate::world::Course;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
 pub enum Color {
@@ -27,7 +34,41 @@ pub enum Color {
     Grey,
 }
 
+impl Color {
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::DarkGrey,
+        Color::Red,
+        Color::DarkRed,
+        Color::Green,
+        Color::DarkGreen,
+        Color::Yellow,
+        Color::DarkYellow,
+        Color::Blue,
+        Color::DarkBlue,
+        Color::Magenta,
+        Color::DarkMagenta,
+        Color::Cyan,
+        Color::DarkCyan,
+        Color::White,
+        Color::Grey,
+    ];
+
+    // the color `step` places further in `ALL`, wrapping around at the ends
+    pub fn cycle(self, step: isize) -> Self {
+        let index = Color::ALL
+            .iter()
+            .position(|color| *color == self)
+            .unwrap_or(0);
+        let len = Color::ALL.len() as isize;
+        Color::ALL[(index as isize + step).rem_euclid(len) as usize]
+    }
+}
+
+// a level either rolls its obstacles with these probabilities or, if it has
+// a course, brings up exactly the obstacles of the course
 #[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
 pub struct Level {
     pub prob_crater_one: f64,
     pub prob_crater_two: f64,
@@ -36,6 +77,8 @@ pub struct Level {
     pub prob_monster_jumping: f64,
     pub points: u16,
     pub desc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub course: Option<Course>,
 }
 
 impl Default for Level {
@@ -49,13 +92,13 @@ impl Default for Level {
             prob_monster_jumping: 0.0,
             points: 100,
             desc: String::default(),
+            course: None,
         }
     }
 }
 
 impl Level {
-    pub fn random() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn random<R: Rng>(rng: &mut R) -> Self {
         Level {
             prob_crater_one: rng.gen_range(0.0..0.5),
             prob_crater_two: rng.gen_range(0.0..0.6),
@@ -64,11 +107,184 @@ impl Level {
             prob_monster_jumping: rng.gen_range(0.0..0.9),
             points: rng.gen_range(20..60),
             desc: String::from("Wohoo! This level is completely random!"),
+            course: None,
+        }
+    }
+}
+
+// environment variables starting with this override options
+const ENV_PREFIX: &str = "MARSROVER_";
+
+// what the options do, written into the default configuration file
+const HEADER: &str = "\
+# Configuration of marsrover, every option can be left out to use its default.
+# Colors are one of Black, DarkGrey, Red, DarkRed, Green, DarkGreen, Yellow,
+# DarkYellow, Blue, DarkBlue, Magenta, DarkMagenta, Cyan, DarkCyan, White, Grey.
+";
+
+pub const OPTIONS: &[(&str, &str)] = &[
+    ("color_context", "the color of the status line"),
+    ("color_ground", "the color of the ground"),
+    ("char_ground", "the character the ground is made of"),
+    ("color_rover", "the color of the rover"),
+    ("color_monster", "the color of the monsters"),
+    ("char_monster", "the character of the monsters"),
+    ("color_monster_jumping", "the color of the jumping monsters"),
+    (
+        "char_monster_jumping",
+        "the character of the jumping monsters",
+    ),
+    ("color_bullet", "the color of the bullets"),
+    ("char_bullet", "the character of the bullets"),
+    ("color_background", "the color of the stars in the sky"),
+    (
+        "tick_rate",
+        "simulation steps per second, this is the speed of the game",
+    ),
+];
+
+const KEYS: &str = "\
+# Every action is bound to a key or a list of keys. Keys are single
+# characters or one of Space, Enter, Esc, Tab, Backspace, Up, Down, Left,
+# Right, Home, End, PageUp, PageDown, Delete and F1 to F12, optionally
+# prefixed with Ctrl+, Alt+ or Shift+.
+";
+
+const LEVELS: &str = "\
+# The levels are played one after the other, after the last one new levels
+# are generated randomly. Instead of the probabilities a level can have a
+# `course` with the exact obstacles, either a strip like \"..._..__..o..O..\"
+# with `.` for the ground, `_` for a crater, `o` for a monster and `O` for a
+# jumping monster, or a list like [{ at = 10, kind = \"crater\", width = 2 }].
+";
+
+const LEVEL_OPTIONS: &[(&str, &str)] = &[
+    (
+        "prob_crater_one",
+        "probability of a crater that is one wide",
+    ),
+    (
+        "prob_crater_two",
+        "probability of a crater that is two wide",
+    ),
+    (
+        "prob_crater_three",
+        "probability of a crater that is three wide",
+    ),
+    ("prob_monster", "probability of a monster"),
+    ("prob_monster_jumping", "probability of a jumping monster"),
+    ("points", "the points it takes to get to the next level"),
+    ("desc", "the message shown when the level starts"),
+];
+
+// a value that can be parsed but makes no sense, `key` is its dotted path
+// in the file, e.g. `levels.2.prob_monster`
+#[derive(Debug)]
+pub struct Problem {
+    pub key: String,
+    pub message: String,
+}
+
+// a problem with the configuration, pointing at where the value came from:
+// a file and the line in it, an environment variable or a `--set` option
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub source: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.source, line, self.message),
+            None => write!(f, "{}: {}", self.source, self.message),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+// one place configuration values come from; the layers are applied on top of
+// the defaults one after the other, so later ones override earlier ones
+struct Layer {
+    source: String,
+    // the text of a file, to find the line of a value in it
+    content: Option<String>,
+    // the dotted key of a single value, e.g. `levels.0.points`
+    key: Option<String>,
+    value: toml::Value,
+}
+
+impl Layer {
+    fn file(path: &Path) -> Result<Layer, Vec<Diagnostic>> {
+        let source = path.display().to_string();
+        let diagnostic = |line: Option<usize>, message: String| Diagnostic {
+            source: source.clone(),
+            line,
+            message,
+        };
+        let content = fs::read_to_string(path)
+            .map_err(|e| vec![diagnostic(None, format!("could not read file: {}", e))])?;
+        // on its own the file is parsed with the positions of the values, so
+        // wrong types can be reported with their line
+        let error = |e: toml::de::Error| {
+            let line = e.span().map(|span| line(&content, span.start));
+            vec![diagnostic(line, e.message().trim().to_string())]
+        };
+        let config = toml::from_str::<Config>(&content).map_err(error)?;
+        let value = toml::from_str(&content).map_err(error)?;
+        let known = toml::Value::try_from(&config).expect("the configuration can be serialized");
+        let unknown = unknown(&value, &known, String::new());
+        if !unknown.is_empty() {
+            let mut diagnostics: Vec<Diagnostic> = unknown
+                .into_iter()
+                .map(|key| {
+                    let message = format!("there is no option `{}`", key);
+                    diagnostic(locate(&content, &key), message)
+                })
+                .collect();
+            diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+            return Err(diagnostics);
+        }
+        Ok(Layer {
+            source,
+            content: Some(content),
+            key: None,
+            value,
+        })
+    }
+
+    // a value that is not valid TOML is taken as a string, so colors and
+    // characters do not have to be quoted
+    fn value(source: String, key: &str, value: &str) -> Layer {
+        let value = format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        Layer {
+            source,
+            content: None,
+            key: Some(key.to_string()),
+            value: key.rsplit('.').fold(value, |value, part| {
+                toml::Value::Table(toml::Table::from_iter([(part.to_string(), value)]))
+            }),
+        }
+    }
+
+    fn diagnostic(&self, key: Option<&str>, message: String) -> Diagnostic {
+        let line = self
+            .content
+            .as_ref()
+            .and_then(|content| locate(content, key?));
+        Diagnostic {
+            source: self.source.clone(),
+            line,
+            message,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
     pub color_context: Color,
@@ -82,7 +298,20 @@ pub struct Config {
     pub color_bullet: Color,
     pub char_bullet: char,
     pub color_background: Color,
+    // simulation ticks per second
+    pub tick_rate: u16,
+    pub keys: Keys,
     pub levels: Vec<Level>,
+    // the user's configuration file, if there is one
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    // where the values were read from, in the order they were applied
+    #[serde(skip)]
+    pub sources: Vec<String>,
+    // the files that are read, to notice when they change; the user's file
+    // is in here even if it does not exist yet
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
 }
 
 impl Default for Config {
@@ -126,32 +355,570 @@ impl Default for Config {
             color_monster_jumping: Color::DarkCyan,
             char_monster_jumping: 'O',
             color_background: Color::DarkYellow,
+            tick_rate: 10,
+            keys: Keys::default(),
             levels: vec![l0, l1, l2, l3, l4],
+            path: None,
+            sources: vec![],
+            files: vec![],
         }
     }
 }
 
 impl Config {
-    pub fn read() -> Config {
-        match xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME")) {
-            Ok(xdg_dirs) => {
-                if let Some(config_path) = xdg_dirs.find_config_file("config.toml") {
-                    let config_content = fs::read_to_string(config_path).unwrap_or_default();
-                    match toml::from_str(&config_content) {
-                        Ok(config) => return config,
-                        Err(e) => eprintln!("Could not parse config file: {}", e),
+    // the user's configuration file in the XDG config directory, if there is one
+    pub fn path() -> Option<PathBuf> {
+        Config::user_path().filter(|path| path.exists())
+    }
+
+    fn user_path() -> Option<PathBuf> {
+        let xdg = xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME")).ok()?;
+        Some(xdg.get_config_home().join("config.toml"))
+    }
+
+    // the system wide configuration files, e.g. in /etc/xdg, the ones with
+    // the lowest precedence first
+    fn system_paths() -> Vec<PathBuf> {
+        let Ok(xdg) = xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME")) else {
+            return vec![];
+        };
+        xdg.get_config_dirs()
+            .iter()
+            .rev()
+            .map(|dir| dir.join("config.toml"))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    // the color option with the given name, e.g. `color_rover`
+    pub fn color_mut(&mut self, name: &str) -> Option<&mut Color> {
+        match name {
+            "color_context" => Some(&mut self.color_context),
+            "color_ground" => Some(&mut self.color_ground),
+            "color_rover" => Some(&mut self.color_rover),
+            "color_monster" => Some(&mut self.color_monster),
+            "color_monster_jumping" => Some(&mut self.color_monster_jumping),
+            "color_bullet" => Some(&mut self.color_bullet),
+            "color_background" => Some(&mut self.color_background),
+            _ => None,
+        }
+    }
+
+    // the character option with the given name, e.g. `char_ground`
+    pub fn char_mut(&mut self, name: &str) -> Option<&mut char> {
+        match name {
+            "char_ground" => Some(&mut self.char_ground),
+            "char_monster" => Some(&mut self.char_monster),
+            "char_monster_jumping" => Some(&mut self.char_monster_jumping),
+            "char_bullet" => Some(&mut self.char_bullet),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&self) -> Duration {
+        Duration::from_secs(1) / u32::from(self.tick_rate.max(1))
+    }
+
+    // the default configuration with a comment on every option
+    pub fn commented() -> String {
+        let content = toml::to_string(&Config::default()).unwrap_or_default();
+        let mut document: toml_edit::DocumentMut = match content.parse() {
+            Ok(document) => document,
+            Err(_) => return content,
+        };
+        let comment = |text: &str| format!("# {}\n", text);
+
+        for (index, (key, text)) in OPTIONS.iter().enumerate() {
+            if let Some(mut key) = document.key_mut(key) {
+                let header = if index == 0 { HEADER } else { "" };
+                key.leaf_decor_mut()
+                    .set_prefix(format!("{}\n{}", header, comment(text)));
+            }
+        }
+        if let Some(keys) = document
+            .get_mut("keys")
+            .and_then(|item| item.as_table_mut())
+        {
+            keys.decor_mut().set_prefix(format!("\n{}", KEYS));
+        }
+        if let Some(levels) = document
+            .get_mut("levels")
+            .and_then(|item| item.as_array_of_tables_mut())
+        {
+            for (index, level) in levels.iter_mut().enumerate() {
+                let intro = if index == 0 {
+                    format!("{}\n", LEVELS)
+                } else {
+                    String::new()
+                };
+                level.decor_mut().set_prefix(format!(
+                    "\n{}{}",
+                    intro,
+                    comment(&format!("level {}", index))
+                ));
+                // the options are the same for every level, so they are
+                // only explained once
+                if index == 0 {
+                    for (key, text) in LEVEL_OPTIONS {
+                        if let Some(mut key) = level.key_mut(key) {
+                            key.leaf_decor_mut().set_prefix(comment(text));
+                        }
                     }
+                }
+            }
+        }
+        document.to_string()
+    }
+
+    // writes the commented default configuration to the given file, or into
+    // the XDG config directory, but never over an existing file
+    pub fn write_default(path: Option<&Path>) -> io::Result<PathBuf> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME"))
+                .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?
+                .place_config_file("config.toml")?,
+        };
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        fs::write(&path, Config::commented())?;
+        Ok(path)
+    }
+
+    // writes the options that differ from `original` to the user's file, or
+    // to the file given with `--config`; the rest of an existing file is
+    // left as it is, comments included
+    pub fn write(&self, original: &Config) -> io::Result<PathBuf> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME"))
+                .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?
+                .place_config_file("config.toml")?,
+        };
+        let invalid =
+            |e: &dyn fmt::Display| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+        let value = |config: &Config| toml::Value::try_from(config).map_err(|e| invalid(&e));
+        let mut changes = vec![];
+        changed(
+            &value(original)?,
+            &value(self)?,
+            String::new(),
+            &mut changes,
+        );
+        if changes.is_empty() {
+            return Ok(path);
+        }
+
+        let mut document = match fs::read_to_string(&path) {
+            Ok(content) => content
+                .parse::<toml_edit::DocumentMut>()
+                .map_err(|e| invalid(&e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml_edit::DocumentMut::new(),
+            Err(e) => return Err(e),
+        };
+        for (key, value) in changes {
+            let value = value
+                .to_string()
+                .parse::<toml_edit::Value>()
+                .map_err(|e| invalid(&e))?;
+            set(document.as_table_mut(), &key, value);
+        }
+        fs::write(&path, document.to_string())?;
+        Ok(path)
+    }
+
+    // puts the configuration together from the defaults, the system wide
+    // files, the user's file (or the given one instead), `MARSROVER_*`
+    // environment variables and `--set` options, in this order; every problem
+    // on the way is reported
+    pub fn read(
+        path: Option<&Path>,
+        overrides: &[(String, String)],
+    ) -> Result<Config, Vec<Diagnostic>> {
+        let user = path.map(Path::to_path_buf).or_else(Config::path);
+        let system = Config::system_paths();
+        let mut layers = vec![];
+        let mut diagnostics = vec![];
+        for file in system.iter().chain(&user) {
+            match Layer::file(file) {
+                Ok(layer) => layers.push(layer),
+                Err(problems) => diagnostics.extend(problems),
+            }
+        }
+        let mut variables: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        variables.sort();
+        for (name, value) in variables {
+            layers.push(Layer::value(name.clone(), &variable_key(&name), &value));
+        }
+        for (key, value) in overrides {
+            layers.push(Layer::value(format!("--set {}={}", key, value), key, value));
+        }
+
+        let mut merged = toml::Value::try_from(Config::default())
+            .expect("the default configuration can be serialized");
+        // the layers that can not be applied are left out, so the rest can
+        // still be checked
+        let mut applied = vec![];
+        for layer in layers {
+            let mut next = merged.clone();
+            merge(&mut next, layer.value.clone());
+            match next.clone().try_into::<Config>() {
+                Ok(config) => {
+                    // a value that is not an option gets lost on the way
+                    // through the configuration and back
+                    if let Some(key) = &layer.key {
+                        let value = toml::Value::try_from(&config).ok();
+                        if value.as_ref().and_then(|value| get(value, key)).is_none() {
+                            diagnostics.push(
+                                layer.diagnostic(None, format!("there is no option `{}`", key)),
+                            );
+                            continue;
+                        }
+                    }
+                    merged = next;
+                    applied.push(layer);
+                }
+                Err(e) => diagnostics.push(layer.diagnostic(None, e.message().trim().to_string())),
+            }
+        }
+        let mut config: Config = merged.try_into().map_err(|e: toml::de::Error| {
+            vec![Diagnostic {
+                source: "configuration".to_string(),
+                line: None,
+                message: e.message().trim().to_string(),
+            }]
+        })?;
+        // a problem is reported where the value was set last
+        for problem in config.validate() {
+            let layer = applied
+                .iter()
+                .rev()
+                .find(|layer| get(&layer.value, &problem.key).is_some());
+            diagnostics.push(match layer {
+                Some(layer) => layer.diagnostic(Some(&problem.key), problem.message),
+                None => Diagnostic {
+                    source: "configuration".to_string(),
+                    line: None,
+                    message: problem.message,
+                },
+            });
+        }
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        config.files = system;
+        config
+            .files
+            .extend(path.map(Path::to_path_buf).or_else(Config::user_path));
+        config.path = user;
+        config.sources = applied.into_iter().map(|layer| layer.source).collect();
+        Ok(config)
+    }
+
+    // checks the values serde can not check for us
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        let mut problem = |key: String, message: String| problems.push(Problem { key, message });
+
+        let chars = [
+            ("char_ground", self.char_ground),
+            ("char_monster", self.char_monster),
+            ("char_monster_jumping", self.char_monster_jumping),
+            ("char_bullet", self.char_bullet),
+        ];
+        for (key, ch) in chars {
+            if ch.is_control() {
+                problem(
+                    key.to_string(),
+                    format!("`{}` has to be a printable character, not {:?}", key, ch),
+                );
+            }
+        }
+        if self.tick_rate == 0 {
+            problem(
+                "tick_rate".to_string(),
+                "`tick_rate` has to be at least 1".to_string(),
+            );
+        }
+        for conflict in self.keys.conflicts() {
+            problem("keys".to_string(), conflict);
+        }
+
+        problems.extend(validate_levels(&self.levels));
+        problems
+    }
+}
+
+// checks the levels of the configuration or of a level pack
+pub(crate) fn validate_levels(levels: &[Level]) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut problem = |key: String, message: String| problems.push(Problem { key, message });
+
+    if levels.is_empty() {
+        problem(
+            "levels".to_string(),
+            "there has to be at least one level".to_string(),
+        );
+    }
+    // the points of a level are counted on top of the ones before it
+    let mut total: u16 = 0;
+    for (index, level) in levels.iter().enumerate() {
+        let probabilities = [
+            ("prob_crater_one", level.prob_crater_one),
+            ("prob_crater_two", level.prob_crater_two),
+            ("prob_crater_three", level.prob_crater_three),
+            ("prob_monster", level.prob_monster),
+            ("prob_monster_jumping", level.prob_monster_jumping),
+        ];
+        for (key, value) in probabilities {
+            if !(0.0..=1.0).contains(&value) {
+                problem(
+                    format!("levels.{}.{}", index, key),
+                    format!(
+                        "`{}` of level {} has to be between 0 and 1, not {}",
+                        key, index, value
+                    ),
+                );
+            }
+        }
+        if level.points == 0 {
+            problem(
+                format!("levels.{}.points", index),
+                format!("`points` of level {} has to be at least 1", index),
+            );
+        }
+        match total.checked_add(level.points) {
+            Some(sum) => total = sum,
+            None => {
+                problem(
+                    format!("levels.{}.points", index),
+                    format!(
+                        "the points of all levels up to level {} add up to more than {}",
+                        index,
+                        u16::MAX
+                    ),
+                );
+                break;
+            }
+        }
+    }
+    problems
+}
+
+// notices when the files of a configuration change, so it can be read again
+// while the game runs
+pub struct Watch {
+    path: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Watch {
+    // `path` and `overrides` are the ones the configuration was read with
+    pub fn new(config: &Config, path: Option<&Path>, overrides: &[(String, String)]) -> Self {
+        Watch {
+            path: path.map(Path::to_path_buf),
+            overrides: overrides.to_vec(),
+            files: Watch::modified(&config.files),
+        }
+    }
+
+    fn modified(files: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+        files
+            .iter()
+            .map(|path| {
+                let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+                (path.clone(), modified)
+            })
+            .collect()
+    }
+
+    // reads the configuration again if one of its files was changed, created
+    // or removed since the last time
+    pub fn poll(&mut self) -> Option<Result<Config, Vec<Diagnostic>>> {
+        let paths: Vec<PathBuf> = self.files.iter().map(|(path, _)| path.clone()).collect();
+        let files = Watch::modified(&paths);
+        if files == self.files {
+            return None;
+        }
+        self.files = files;
+        let config = Config::read(self.path.as_deref(), &self.overrides);
+        if let Ok(config) = &config {
+            self.files = Watch::modified(&config.files);
+        }
+        Some(config)
+    }
+}
+
+// the offset of the value at a dotted path like `levels.2.points`, or of the
+// closest table on the way if the value is not in the file
+fn span(table: &toml_edit::Table, key: &str) -> Option<usize> {
+    let (first, rest) = match key.split_once('.') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (key, None),
+    };
+    let (name, item) = table.get_key_value(first)?;
+    let found = match (item, rest) {
+        (toml_edit::Item::Table(table), Some(rest)) => span(table, rest),
+        (toml_edit::Item::ArrayOfTables(tables), Some(rest)) => {
+            let (index, rest) = match rest.split_once('.') {
+                Some((index, rest)) => (index, Some(rest)),
+                None => (rest, None),
+            };
+            let table = tables.get(index.parse().ok()?)?;
+            rest.and_then(|rest| span(table, rest))
+                .or_else(|| table.span().map(|span| span.start))
+        }
+        _ => None,
+    };
+    found.or_else(|| name.span().map(|span| span.start))
+}
+
+// collects the dotted keys of a file that did not make it into what was read
+// from it, e.g. misspelled options, which serde would skip without a word
+pub(crate) fn unknown(file: &toml::Value, known: &toml::Value, key: String) -> Vec<String> {
+    let join = |name: String| {
+        if key.is_empty() {
+            name
+        } else {
+            format!("{}.{}", key, name)
+        }
+    };
+    match (file, known) {
+        (toml::Value::Table(file), toml::Value::Table(known)) => file
+            .iter()
+            .flat_map(|(name, value)| match known.get(name) {
+                Some(known) => unknown(value, known, join(name.clone())),
+                None => vec![join(name.clone())],
+            })
+            .collect(),
+        (toml::Value::Array(file), toml::Value::Array(known)) => file
+            .iter()
+            .zip(known)
+            .enumerate()
+            .flat_map(|(index, (value, known))| unknown(value, known, join(index.to_string())))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// collects the dotted keys of the values that differ between two
+// configurations; lists are compared as a whole
+fn changed(
+    original: &toml::Value,
+    value: &toml::Value,
+    key: String,
+    changes: &mut Vec<(String, toml::Value)>,
+) {
+    match (original, value) {
+        (toml::Value::Table(original), toml::Value::Table(table)) => {
+            for (name, value) in table {
+                let key = if key.is_empty() {
+                    name.clone()
                 } else {
-                    //for now disabled, should only be shown with some kind of --debug flag
-                    //eprintln!("Could not load configuration file, using default settings.");
+                    format!("{}.{}", key, name)
+                };
+                match original.get(name) {
+                    Some(original) => changed(original, value, key, changes),
+                    None => changes.push((key, value.clone())),
                 }
             }
-            Err(e) => eprintln!("Cannot determine XDG base directories: {}", e),
         }
-        Config::default()
+        (original, value) if original != value => changes.push((key, value.clone())),
+        _ => (),
     }
 }
 
+// sets the value at a dotted path like `keys.jump`, an existing value keeps
+// the comments around it
+fn set(table: &mut dyn toml_edit::TableLike, key: &str, value: toml_edit::Value) {
+    match key.split_once('.') {
+        Some((first, rest)) => {
+            if table.get(first).is_none() {
+                table.insert(first, toml_edit::table());
+            }
+            if let Some(table) = table
+                .get_mut(first)
+                .and_then(|item| item.as_table_like_mut())
+            {
+                set(table, rest, value);
+            }
+        }
+        None => match table.get_mut(key).and_then(|item| item.as_value_mut()) {
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = value;
+                *old.decor_mut() = decor;
+            }
+            None => {
+                table.insert(key, toml_edit::value(value));
+            }
+        },
+    }
+}
+
+// the option an environment variable sets; nested keys are separated by two
+// underscores, e.g. MARSROVER_KEYS__JUMP or MARSROVER_LEVELS__0__POINTS
+fn variable_key(name: &str) -> String {
+    name[ENV_PREFIX.len()..].to_lowercase().replace("__", ".")
+}
+
+// the value at a dotted path like `levels.2.points`
+fn get<'a>(value: &'a toml::Value, key: &str) -> Option<&'a toml::Value> {
+    key.split('.').try_fold(value, |value, part| match value {
+        toml::Value::Table(table) => table.get(part),
+        toml::Value::Array(array) => array.get(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+// tables are merged key by key, so a layer can change a single option; a
+// table with numbers as keys changes single elements of a list, everything
+// else is replaced
+fn merge(base: &mut toml::Value, layer: toml::Value) {
+    match (base, layer) {
+        (toml::Value::Table(base), toml::Value::Table(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (toml::Value::Array(base), toml::Value::Table(layer)) => {
+            for (key, value) in layer {
+                if let Some(base) = key
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| base.get_mut(index))
+                {
+                    merge(base, value);
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+// the line of the value at a dotted path in a file
+pub(crate) fn locate(content: &str, key: &str) -> Option<usize> {
+    let document = toml_edit::ImDocument::parse(content).ok()?;
+    span(document.as_table(), key).map(|offset| line(content, offset))
+}
+
+// the line number of a byte offset, counting from 1
+pub(crate) fn line(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
 use crossterm::style::Color as CTC;
 
 impl From<Color> for crossterm::style::Color {
@@ -176,3 +943,204 @@ impl From<Color> for crossterm::style::Color {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Action;
+
+    // a configuration file that is removed again when the test is done
+    struct File(PathBuf);
+
+    impl File {
+        fn new(name: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "marsrover-{}-{}.toml",
+                name,
+                std::process::id()
+            ));
+            fs::write(&path, content).unwrap();
+            File(path)
+        }
+    }
+
+    impl Drop for File {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn points_have_to_add_up_to_a_number() {
+        let level = Level {
+            points: 40000,
+            ..Default::default()
+        };
+        let problems = validate_levels(&[level.clone(), level]);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "levels.1.points");
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_level() {
+        let file = File::new(
+            "bad-level",
+            "tick_rate = 20\n\n[[levels]]\npoints = 5\n\n[[levels]]\nprob_monster = 2.0\n",
+        );
+        let diagnostics = Config::read(Some(&file.0), &[]).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].source, file.0.display().to_string());
+        assert_eq!(diagnostics[0].line, Some(7));
+        assert!(diagnostics[0].message.contains("prob_monster"));
+    }
+
+    #[test]
+    fn reports_the_line_of_a_wrong_type() {
+        let file = File::new(
+            "wrong-type",
+            "[[levels]]\n\n[[levels]]\npoints = \"many\"\n",
+        );
+        let diagnostics = Config::read(Some(&file.0), &[]).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, Some(4));
+    }
+
+    #[test]
+    fn names_options_after_variables() {
+        assert_eq!(variable_key("MARSROVER_TICK_RATE"), "tick_rate");
+        assert_eq!(variable_key("MARSROVER_KEYS__JUMP"), "keys.jump");
+        assert_eq!(
+            variable_key("MARSROVER_LEVELS__0__POINTS"),
+            "levels.0.points"
+        );
+    }
+
+    #[test]
+    fn layers_change_single_elements_of_lists() {
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        let layer = Layer::value("test".to_string(), "levels.2.points", "7");
+        assert_eq!(layer.key.as_deref(), Some("levels.2.points"));
+        merge(&mut value, layer.value);
+        merge(
+            &mut value,
+            Layer::value("test".to_string(), "color_rover", "Red").value,
+        );
+        // an index past the end of the list is left out
+        merge(
+            &mut value,
+            Layer::value("test".to_string(), "levels.9.points", "7").value,
+        );
+        assert_eq!(
+            get(&value, "levels.2.points"),
+            Some(&toml::Value::Integer(7))
+        );
+        assert_eq!(get(&value, "levels.9.points"), None);
+
+        let config: Config = value.try_into().unwrap();
+        let default = Config::default();
+        assert_eq!(config.levels.len(), default.levels.len());
+        assert_eq!(config.levels[2].points, 7);
+        assert_eq!(config.levels[1].points, default.levels[1].points);
+        assert_eq!(config.levels[2].desc, default.levels[2].desc);
+        assert!(matches!(config.color_rover, Color::Red));
+    }
+
+    #[test]
+    fn overrides_apply_on_top_of_the_file() {
+        let file = File::new(
+            "overrides",
+            "[[levels]]\npoints = 5\n\n[[levels]]\npoints = 6\n",
+        );
+        let overrides = [("levels.1.points".to_string(), "9".to_string())];
+        let config = Config::read(Some(&file.0), &overrides).unwrap();
+        assert_eq!(config.levels.len(), 2);
+        assert_eq!(config.levels[0].points, 5);
+        assert_eq!(config.levels[1].points, 9);
+        assert_eq!(config.sources.last().unwrap(), "--set levels.1.points=9");
+
+        let overrides = [("levels.0.speed".to_string(), "9".to_string())];
+        let diagnostics = Config::read(Some(&file.0), &overrides).unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "there is no option `levels.0.speed`"
+        );
+    }
+
+    #[test]
+    fn reports_misspelled_options_with_their_line() {
+        let file = File::new(
+            "misspelled",
+            "tick_rate = 20\n[keys]\njmup = \"Up\"\n\n[[levels]]\nprob_monstr = 0.9\n",
+        );
+        let diagnostics = Config::read(Some(&file.0), &[]).unwrap_err();
+        let reported: Vec<(Option<usize>, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (Some(3), "there is no option `keys.jmup`"),
+                (Some(6), "there is no option `levels.0.prob_monstr`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_the_default_file() {
+        let file = File::new("default", &Config::commented());
+        let config = Config::read(Some(&file.0), &[]).unwrap();
+        assert_eq!(config.levels.len(), Config::default().levels.len());
+    }
+
+    #[test]
+    fn writes_only_the_changed_options() {
+        let content = "\
+# my colors
+color_rover = \"Red\" # the best one
+tick_rate = 30
+
+[keys]
+# vim style
+jump = \"k\"
+
+[[levels]]
+points = 5 # short
+";
+        let file = File::new("write", content);
+        let original = Config::read(Some(&file.0), &[]).unwrap();
+        let mut config = original.clone();
+        *config.color_mut("color_rover").unwrap() = Color::Blue;
+        *config.keys.keys_mut(Action::Shoot) = vec!["l".parse().unwrap()];
+        assert_eq!(config.write(&original).unwrap(), file.0);
+
+        let written = fs::read_to_string(&file.0).unwrap();
+        assert_eq!(
+            written,
+            "\
+# my colors
+color_rover = \"Blue\" # the best one
+tick_rate = 30
+
+[keys]
+# vim style
+jump = \"k\"
+shoot = [\"l\"]
+
+[[levels]]
+points = 5 # short
+"
+        );
+        let read = Config::read(Some(&file.0), &[]).unwrap();
+        assert!(matches!(read.color_rover, Color::Blue));
+        assert_eq!(read.levels.len(), 1);
+    }
+
+    #[test]
+    fn locates_values_in_lists_of_tables() {
+        let content = "[[levels]]\npoints = 1\n\n[[levels]]\npoints = 2\ndesc = \"two\"\n";
+        assert_eq!(locate(content, "levels.0.points"), Some(2));
+        assert_eq!(locate(content, "levels.1.desc"), Some(6));
+        assert_eq!(locate(content, "levels.2.points"), None);
+    }
+}
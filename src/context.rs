@@ -2,18 +2,47 @@
 //
 // SPDX-License-Identifier: MIT
 
-use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::config::Config;
-use crate::world::World;
+use crate::config::{Color, Config, Level};
+use crate::render::{Cell, Grid};
+use crate::world::{Event, World};
 
 #[derive(PartialEq)]
 pub enum State {
     Run,
+    Paused,
     Quit,
 }
 
+// the actions of one tick are applied in this order, so shooting and
+// jumping at once fires the bullet before the rover leaves the ground
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Action {
+    Shoot,
+    Jump,
+    Quit,
+    Pause,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+pub enum Mode {
+    #[default]
+    Classic,
+    // the rover never runs out of lives and the run is not scored
+    Practice,
+}
+
+impl Mode {
+    pub fn next(self) -> Self {
+        match self {
+            Mode::Classic => Mode::Practice,
+            Mode::Practice => Mode::Classic,
+        }
+    }
+}
+
 pub struct Message {
     pub message: String,
     pub tick: u16,
@@ -21,116 +50,198 @@ pub struct Message {
 
 pub struct Context {
     pub state: State,
+    pub mode: Mode,
     pub config: Config,
     pub level: usize,
     pub messages: Vec<Message>,
     pub world: World,
+    // the levels of the configuration, `config.levels` also has the ones that
+    // were generated randomly during the run
+    pub configured_levels: Vec<Level>,
 }
 
 impl Default for Context {
     fn default() -> Self {
+        Context::new(rand::random(), Config::read(None, &[]).unwrap_or_default())
+    }
+}
+
+impl Context {
+    pub fn new(seed: u64, config: Config) -> Self {
         Context {
             state: State::Run,
-            config: Config::read(),
+            mode: Mode::default(),
+            configured_levels: config.levels.clone(),
+            config,
             level: 0,
             messages: vec![],
-            world: World::default(),
+            world: World::new(seed),
         }
     }
-}
 
-impl Context {
     pub fn run(&self) -> bool {
-        self.state == State::Run && self.world.rover.lives > 0
+        self.state != State::Quit && self.world.rover.lives > 0
     }
     pub fn quit(&mut self) {
         self.state = State::Quit;
     }
+    pub fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            State::Run => State::Paused,
+            State::Paused => State::Run,
+            State::Quit => State::Quit,
+        };
+    }
+    // starts a new run with the same configuration; a run that starts at a
+    // later level gets the points of the levels it skipped
+    pub fn restart(&mut self, seed: u64, level: usize) {
+        self.state = State::Run;
+        self.world = World::new(seed);
+        self.config.levels = self.configured_levels.clone();
+        self.level = level.min(self.config.levels.len() - 1);
+        self.world.rover.points = self.config.levels[..self.level]
+            .iter()
+            .map(|level| level.points)
+            .sum();
+        self.messages.clear();
+    }
+    // plays these levels from the next run on, e.g. the ones of a level pack
+    pub fn set_levels(&mut self, levels: Vec<Level>) {
+        self.configured_levels = levels;
+    }
+    // takes over a changed configuration without ending the run; the levels
+    // up to the current one stay as they are, the next ones are the new ones
+    pub fn reload(&mut self, mut config: Config) {
+        let played = self.level + 1;
+        let mut levels = self.config.levels[..played].to_vec();
+        levels.extend(config.levels.iter().skip(played).cloned());
+        self.configured_levels = std::mem::replace(&mut config.levels, levels);
+        self.config = config;
+    }
+    pub fn step(&mut self, cols: u16, rows: u16, actions: &[Action]) -> Vec<Event> {
+        if actions.contains(&Action::Quit) {
+            self.quit();
+        }
+        let events = self
+            .world
+            .step(cols, rows, actions, &self.config.levels, self.level);
+        if self.mode == Mode::Practice
+            && events
+                .iter()
+                .any(|event| matches!(event, Event::Crashed | Event::MonsterCrashed))
+        {
+            self.world.rover.lives += 1;
+        }
+        if events.contains(&Event::LevelUp) {
+            if self.level < self.config.levels.len() - 1 {
+                self.level += 1;
+                self.addmessage(format!("Level up! You're now on level {}", self.level), 40);
+                self.addmessage(self.config.levels[self.level].desc.to_string(), 40);
+            } else {
+                self.config.levels.push(Level::random(&mut self.world.rng));
+            }
+        }
+
+        self.messages
+            .iter_mut()
+            .for_each(|message| message.tick = message.tick.saturating_sub(1));
+        self.messages.retain(|message| message.tick > 0);
+        events
+    }
     pub fn addmessage(&mut self, message: String, tick: u16) {
         let msg = Message { message, tick };
         self.messages.push(msg);
     }
 
-    pub fn worldtolines(&self) -> Vec<String> {
-        let rows = self.world.rows.into();
-        let cols = self.world.cols.into();
-        let mut worldlines = vec![vec![' '.to_string(); cols]; rows];
-
-        worldlines[rows - 2] = vec![
-            self.config
-                .char_ground
-                .to_string()
-                .with(self.config.color_ground.into())
-                .to_string();
-            cols
-        ];
-        worldlines[rows - 3] = vec![
-            self.config
-                .char_ground
-                .to_string()
-                .with(self.config.color_ground.into())
-                .to_string();
-            cols
-        ];
+    pub fn rasterize(&self, grid: &mut Grid) {
+        let rows = self.world.rows;
+        let cols = self.world.cols;
+        let cell = |ch: char, fg: Color| Cell { ch, fg, bg: None };
+        // nothing fits on the screen before the world got its first size
+        if cols < World::MIN_COLS || rows < World::MIN_ROWS {
+            return;
+        }
+
+        grid.print(0, rows - 1, &self.to_string(), self.config.color_context);
+
+        for col in 0..cols {
+            grid.set(
+                col,
+                rows - 3,
+                cell(self.config.char_ground, self.config.color_ground),
+            );
+            grid.set(
+                col,
+                rows - 2,
+                cell(self.config.char_ground, self.config.color_ground),
+            );
+        }
         for crater in &self.world.craters {
-            if (crater.col as usize) < cols && (crater.row as usize) < rows {
-                worldlines[rows - 3][crater.col as usize] = ' '.to_string();
+            if crater.row < rows {
+                grid.set(crater.col, rows - 3, Cell::default());
             }
         }
-        let monsters = self
-            .world
-            .monsters
-            .iter()
-            .filter(|monster| (monster.col as usize) < cols && (monster.row as usize) < rows);
-        for monster in monsters {
+        for monster in &self.world.monsters {
             if monster.jumping.is_some() {
-                worldlines[monster.row as usize][monster.col as usize] = self
-                    .config
-                    .char_monster_jumping
-                    .to_string()
-                    .with(self.config.color_monster_jumping.into())
-                    .to_string();
+                grid.set(
+                    monster.col,
+                    monster.row,
+                    cell(
+                        self.config.char_monster_jumping,
+                        self.config.color_monster_jumping,
+                    ),
+                );
             } else {
-                worldlines[monster.row as usize][monster.col as usize] = self
-                    .config
-                    .char_monster
-                    .to_string()
-                    .with(self.config.color_monster.into())
-                    .to_string();
+                grid.set(
+                    monster.col,
+                    monster.row,
+                    cell(self.config.char_monster, self.config.color_monster),
+                );
             }
         }
         for bullet in &self.world.bullets {
-            if (bullet.col as usize) < cols && (bullet.row as usize) < rows {
-                worldlines[bullet.row as usize][bullet.col as usize] = self
-                    .config
-                    .char_bullet
-                    .to_string()
-                    .with(self.config.color_bullet.into())
-                    .to_string();
-            }
+            grid.set(
+                bullet.col,
+                bullet.row,
+                cell(self.config.char_bullet, self.config.color_bullet),
+            );
         }
         for bg in &self.world.backgrounds {
-            if (bg.col as usize) < cols && (bg.row as usize) < rows {
-                worldlines[bg.row as usize][bg.col as usize] = bg
-                    .chr
-                    .to_string()
-                    .with(self.config.color_background.into())
-                    .to_string();
-            }
+            grid.set(bg.col, bg.row, cell(bg.chr, self.config.color_background));
+        }
+
+        /* draw the rover */
+        let bstr: String = self.world.rover.into();
+        for (index, line) in bstr.lines().rev().enumerate() {
+            grid.print(
+                self.world.rover.col,
+                self.world.rover.row() - index as u16,
+                line,
+                self.config.color_rover,
+            );
+        }
+
+        // print messages, if any
+        for (index, message) in self.messages.iter().rev().enumerate() {
+            let pos = rows.saturating_sub(20 + index as u16);
+            grid.print(20, pos, &message.message, Color::White);
         }
-        worldlines.iter().map(|rowvec| rowvec.join("")).collect()
     }
 }
 
 impl fmt::Display for Context {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Classic => write!(f, "Lives: {} ", self.world.rover.lives)?,
+            Mode::Practice => write!(f, "Practice ")?,
+        }
         write!(
             f,
-            "Lives: {} Points: {}, Level: {}/{}",
-            self.world.rover.lives,
+            "Points: {}, Level: {}/{}, Seed: {}",
             self.world.rover.points,
             self.level,
-            self.config.levels.len()
+            self.config.levels.len(),
+            self.world.seed
         )
     }
 }
@@ -2,39 +2,99 @@
 //
 // SPDX-License-Identifier: MIT
 
-use crossterm::event::{self, poll, Event, KeyCode, KeyEvent};
+use crossterm::event::{self, poll, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::collections::BTreeSet;
 use std::io;
 use std::time::Duration;
 
-use crate::context;
+use marsrover::context::Action;
+use marsrover::keys::Keys;
 
-pub fn events(ctx: &mut context::Context) -> io::Result<()> {
-    if poll(Duration::from_millis(100))? {
-        if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char(' ') => ctx.world.rover.jump(),
-                KeyCode::Char('j') => ctx.world.shoot(),
-                KeyCode::Char('q') => ctx.quit(),
+use crate::session;
+
+// the events that were read in one go
+#[derive(Default)]
+pub struct Input {
+    pub keys: Vec<KeyEvent>,
+    // the new size of the terminal, if it was resized
+    pub size: Option<(u16, u16)>,
+    // whether the terminal gained or lost the focus
+    pub focus: Option<bool>,
+}
+
+impl Input {
+    fn key(&mut self, key: KeyEvent) -> io::Result<()> {
+        // raw mode turns Ctrl-Z into a plain key, so the suspend has to be
+        // done by hand
+        if key.code == KeyCode::Char('z')
+            && key.modifiers == KeyModifiers::CONTROL
+            && key.kind == KeyEventKind::Press
+        {
+            session::suspend()?;
+        } else if key.kind != KeyEventKind::Release {
+            self.keys.push(key);
+        }
+        Ok(())
+    }
+}
+
+// waits at most `timeout` for input and then drains every event that is
+// already queued, so quick combos are not spread over several ticks
+pub fn read(timeout: Duration) -> io::Result<Input> {
+    let mut input = Input::default();
+    if poll(timeout)? {
+        loop {
+            match event::read()? {
+                Event::Key(key) => input.key(key)?,
+                Event::Resize(cols, rows) => input.size = Some((cols, rows)),
+                Event::FocusGained => input.focus = Some(true),
+                Event::FocusLost => input.focus = Some(false),
                 _ => (),
             }
+            if !poll(Duration::ZERO)? {
+                break;
+            }
         }
     }
-    Ok(())
+    if let Some(signal) = session::terminated() {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            format!("terminated by signal {}", signal),
+        ));
+    }
+    Ok(input)
+}
+
+// like `read`, for screens that only care about the keys
+pub fn keys(timeout: Duration) -> io::Result<Vec<KeyEvent>> {
+    Ok(read(timeout)?.keys)
+}
+
+// maps the pressed keys to the actions bound to them
+pub fn actions(keys: &Keys, pressed: &[KeyEvent]) -> BTreeSet<Action> {
+    pressed
+        .iter()
+        .filter_map(|key| match (keys.action(key), key.kind) {
+            // holding the shoot key keeps firing, but a held jump key must
+            // not toggle the jump on every repeat
+            (Some(Action::Shoot), _) => Some(Action::Shoot),
+            (action, KeyEventKind::Press) => action,
+            _ => None,
+        })
+        .collect()
 }
 
-pub fn read_name(line: &mut String) -> bool {
-    if poll(Duration::from_millis(100)).is_ok() {
-        if let Ok(Event::Key(KeyEvent { code, .. })) = event::read() {
-            match code {
-                KeyCode::Enter => {
-                    return false;
-                }
-                KeyCode::Char(c) => {
-                    line.push(c);
-                }
-                _ => {}
+// edits the name the player types, returns false once it is entered
+pub fn read_name(line: &mut String) -> io::Result<bool> {
+    for key in keys(Duration::from_millis(100))? {
+        match key.code {
+            KeyCode::Enter => return Ok(false),
+            KeyCode::Backspace => {
+                line.pop();
             }
+            KeyCode::Char(c) => line.push(c),
+            _ => (),
         }
     }
-    true
+    Ok(true)
 }
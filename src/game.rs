@@ -0,0 +1,732 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use crossterm::event::KeyCode;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use marsrover::config::Color;
+use marsrover::config::{Config, Diagnostic, Watch, OPTIONS};
+use marsrover::context::{Action, Context, Mode, State};
+use marsrover::keys::Key;
+use marsrover::menu::Menu;
+use marsrover::pack::Pack;
+use marsrover::render::{Grid, Renderer};
+use marsrover::replay::Replay;
+use marsrover::scores;
+use marsrover::world::World;
+
+use crate::cli::PlayArgs;
+use crate::events;
+use crate::session;
+
+const LOGO: &str = r"
+ _ __ ___   __ _ _ __ ___ _ __ _____   _____ _ __
+| '_ ` _ \ / _` | '__/ __| '__/ _ \ \ / / _ \ '__|
+| | | | | | (_| | |  \__ \ | | (_) \ V /  __/ |
+|_| |_| |_|\__,_|_|  |___/_|  \___/ \_/ \___|_|
+";
+
+#[derive(Clone, Copy)]
+enum PauseChoice {
+    Resume,
+    Restart,
+    Quit,
+}
+
+#[derive(Clone, Copy)]
+pub enum TitleChoice {
+    Start,
+    Mode,
+    Pack,
+    Scores,
+    Settings,
+    Quit,
+}
+
+#[derive(Clone, Copy)]
+pub enum GameOverChoice {
+    PlayAgain,
+    MainMenu,
+    Quit,
+}
+
+pub struct Game<R: Renderer> {
+    pub ctx: Context,
+    renderer: R,
+    grid: Grid,
+    // the size of the terminal, as of the last resize
+    size: (u16, u16),
+    // the seed given on the command line, used for every run
+    seed: Option<u64>,
+    // the level every run starts at
+    level: usize,
+    // the levels of the configuration come first, followed by the packs
+    packs: Vec<Pack>,
+    pack: usize,
+    pub playback: Option<Replay>,
+    // reads the configuration again when it changes, but not while a replay
+    // is played or recorded, as it would not match the recorded levels
+    watch: Option<Watch>,
+    record: Option<PathBuf>,
+    recording: Replay,
+    pause: Menu<PauseChoice>,
+    // the game was paused because the terminal lost the focus
+    away: bool,
+    title: Menu<TitleChoice>,
+    // problems that can only be shown once the terminal is restored
+    pub errors: Vec<String>,
+}
+
+impl<R: Renderer> Game<R> {
+    pub fn new(
+        renderer: R,
+        config: Config,
+        packs: Vec<Pack>,
+        pack: usize,
+        args: PlayArgs,
+        playback: Option<Replay>,
+        watch: Watch,
+    ) -> Self {
+        let mut ctx = Context::new(args.seed.unwrap_or_else(rand::random), config);
+        if let Some(replay) = &playback {
+            ctx.mode = replay.mode;
+        }
+        let mut game = Game {
+            recording: Replay::default(),
+            ctx,
+            renderer,
+            grid: Grid::default(),
+            size: (0, 0),
+            seed: args.seed,
+            level: args.level,
+            packs,
+            pack,
+            watch: (playback.is_none() && args.record.is_none()).then_some(watch),
+            playback,
+            record: args.record,
+            pause: Menu::new(
+                "Paused",
+                vec![
+                    ("Resume".to_string(), PauseChoice::Resume),
+                    ("Restart".to_string(), PauseChoice::Restart),
+                    ("Quit".to_string(), PauseChoice::Quit),
+                ],
+            ),
+            away: false,
+            title: Menu::new(
+                "",
+                vec![
+                    ("Start game".to_string(), TitleChoice::Start),
+                    (String::new(), TitleChoice::Mode),
+                    (String::new(), TitleChoice::Pack),
+                    ("Scoreboard".to_string(), TitleChoice::Scores),
+                    ("Settings".to_string(), TitleChoice::Settings),
+                    ("Quit".to_string(), TitleChoice::Quit),
+                ],
+            ),
+            errors: vec![],
+        };
+        game.select(pack);
+        if let Some(replay) = &game.playback {
+            if !replay.levels.is_empty() {
+                game.ctx.set_levels(replay.levels.clone());
+            }
+        }
+        game.restart();
+        if game.playback.is_some() {
+            game.ctx
+                .addmessage("Replaying a recorded run, press q to stop".to_string(), 40);
+        }
+        game
+    }
+
+    pub fn restart(&mut self) {
+        let (seed, level) = match &self.playback {
+            Some(replay) => (replay.seed, replay.level),
+            None => (self.seed.unwrap_or_else(rand::random), self.level),
+        };
+        self.ctx.restart(seed, level);
+        self.away = false;
+        self.recording = Replay::new(
+            seed,
+            self.ctx.mode,
+            self.ctx.level,
+            self.pack_name(),
+            self.ctx.configured_levels.clone(),
+        );
+    }
+
+    // plays the levels of a pack, or the configured ones for the first one
+    fn select(&mut self, pack: usize) {
+        self.pack = pack;
+        self.ctx.set_levels(self.packs[pack].levels.clone());
+    }
+
+    // the name the pack is known by on the scoreboard and in replays
+    fn pack_name(&self) -> Option<String> {
+        (self.pack > 0).then(|| self.packs[self.pack].name.clone())
+    }
+
+    // runs the game until the player quits or runs out of lives
+    pub fn play(&mut self) -> io::Result<()> {
+        let mut tick = self.ctx.config.tick();
+        let mut next_tick = Instant::now();
+        self.resize(self.renderer.size()?);
+        while self.ctx.run() {
+            // gather the input until the next tick is due, so the simulation
+            // runs at the same speed no matter how many keys are pressed
+            let mut pressed = vec![];
+            let mut focus_lost = false;
+            let mut now = Instant::now();
+            while now < next_tick {
+                let input = events::read(next_tick - now)?;
+                pressed.extend(input.keys);
+                if let Some(size) = input.size {
+                    self.resize(size);
+                }
+                focus_lost |= input.focus == Some(false);
+                now = Instant::now();
+            }
+            // don't try to catch up if we fell behind, e.g. after a suspend
+            next_tick = (next_tick + tick).max(now);
+            if let Some(config) = self.watch.as_mut().and_then(Watch::poll) {
+                self.reload(config);
+                tick = self.ctx.config.tick();
+            }
+            let input = events::actions(&self.ctx.config.keys, &pressed);
+
+            // the rover must not drive on while the player looks elsewhere
+            if focus_lost && self.ctx.state == State::Run {
+                self.ctx.toggle_pause();
+                self.away = true;
+            }
+
+            // the game waits until the terminal is big enough again
+            let (cols, rows) = self.size;
+            if cols < World::MIN_COLS || rows < World::MIN_ROWS {
+                if input.contains(&Action::Quit) {
+                    self.ctx.quit();
+                }
+                self.too_small()?;
+                continue;
+            }
+
+            if self.away {
+                if !pressed.is_empty() {
+                    self.ctx.toggle_pause();
+                    self.away = false;
+                }
+            } else if self.ctx.state == State::Paused {
+                if input.contains(&Action::Quit) {
+                    self.ctx.quit();
+                } else if input.contains(&Action::Pause) {
+                    self.ctx.toggle_pause();
+                } else {
+                    match pressed.iter().find_map(|key| self.pause.handle(key)) {
+                        Some(PauseChoice::Resume) => self.ctx.toggle_pause(),
+                        Some(PauseChoice::Restart) => self.restart(),
+                        Some(PauseChoice::Quit) => self.ctx.quit(),
+                        None => (),
+                    }
+                }
+            } else if input.contains(&Action::Pause) {
+                self.ctx.toggle_pause();
+                self.pause.selected = 0;
+            } else {
+                let frame = self.ctx.world.frame;
+                let (cols, rows) = match &self.playback {
+                    Some(replay) => {
+                        for (cols, rows) in replay.resizes(frame) {
+                            self.ctx.world.resize(cols, rows);
+                        }
+                        replay.size(frame).unwrap_or(self.size)
+                    }
+                    None => self.size,
+                };
+                self.recording.record_size(frame, cols, rows);
+
+                let actions: Vec<Action> = match &self.playback {
+                    // during playback the keyboard can only be used to stop the replay
+                    Some(replay) => replay
+                        .actions(frame)
+                        .chain(input.into_iter().filter(|action| *action == Action::Quit))
+                        .collect(),
+                    None => input.into_iter().collect(),
+                };
+                for action in &actions {
+                    self.recording.record(frame, *action);
+                }
+                self.ctx.step(cols, rows, &actions);
+            }
+
+            self.rasterize();
+            if self.away {
+                self.grid.dim();
+                Menu::<()>::new("Press any key to continue", vec![]).rasterize(&mut self.grid);
+            } else if self.ctx.state == State::Paused {
+                self.grid.dim();
+                self.pause.rasterize(&mut self.grid);
+            }
+            self.render()?;
+        }
+        Ok(())
+    }
+
+    // applies a changed configuration to the run, or tells why it was not
+    fn reload(&mut self, config: Result<Config, Vec<Diagnostic>>) {
+        match config {
+            Ok(mut config) => {
+                self.packs[0].levels = config.levels.clone();
+                if self.pack > 0 {
+                    config.levels = self.packs[self.pack].levels.clone();
+                }
+                self.ctx.reload(config);
+                self.ctx
+                    .addmessage("Reloaded the configuration".to_string(), 40);
+            }
+            Err(diagnostics) => {
+                self.ctx.addmessage(
+                    "The configuration has problems, keeping the old one:".to_string(),
+                    80,
+                );
+                for diagnostic in diagnostics.iter().take(3) {
+                    self.ctx.addmessage(diagnostic.to_string(), 80);
+                }
+            }
+        }
+    }
+
+    fn resize(&mut self, size: (u16, u16)) {
+        self.size = size;
+        // during playback the world keeps the recorded size; otherwise it is
+        // reflowed right away, so a paused game is drawn at the new size too,
+        // and every reflow is recorded to be done again on playback
+        let (cols, rows) = size;
+        if self.playback.is_none() && cols >= World::MIN_COLS && rows >= World::MIN_ROWS {
+            self.ctx.world.resize(cols, rows);
+            self.recording.record_size(self.ctx.world.frame, cols, rows);
+        }
+    }
+
+    fn too_small(&mut self) -> io::Result<()> {
+        self.blank()?;
+        let (cols, rows) = self.size;
+        let lines = [
+            "Terminal too small".to_string(),
+            format!(
+                "{}x{}, needs {}x{}",
+                cols,
+                rows,
+                World::MIN_COLS,
+                World::MIN_ROWS
+            ),
+        ];
+        for (index, line) in lines.iter().enumerate() {
+            let col = cols.saturating_sub(line.len() as u16) / 2;
+            let row = (rows / 2 + index as u16).saturating_sub(1);
+            self.grid.print(col, row, line, Color::White);
+        }
+        self.render()
+    }
+
+    fn render(&mut self) -> io::Result<()> {
+        // the screen is gone after a suspend and has to be drawn from scratch
+        if session::resumed() {
+            self.renderer.invalidate();
+        }
+        self.renderer.render(&self.grid)
+    }
+
+    fn rasterize(&mut self) {
+        // the grid always covers the terminal, a replayed world that is
+        // bigger than that is cut off
+        let (cols, rows) = self.size;
+        self.grid.resize(cols, rows);
+        self.grid.clear();
+        self.ctx.rasterize(&mut self.grid);
+    }
+
+    // writes the replay file and lets the player enter the scoreboard
+    pub fn finish(&mut self) -> io::Result<()> {
+        let points = self.ctx.world.rover.points;
+        if let Some(path) = &self.record {
+            self.recording.points = points;
+            if let Err(e) = self.recording.write(path) {
+                self.errors.push(format!(
+                    "Could not write replay file {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        }
+
+        // a replayed run must not end up on the scoreboard a second time,
+        // neither does one that skipped levels
+        if self.playback.is_some()
+            || self.ctx.mode == Mode::Practice
+            || self.level > 0
+            || points == 0
+        {
+            return Ok(());
+        }
+        let mut scores = scores::Scores::read();
+        let pack = self.pack_name();
+
+        self.grid.print(10, 2, "Scoreboard:", Color::White);
+        for (index, score) in scores.pack(pack.as_deref()).enumerate() {
+            let score: String = score.into();
+            self.grid.print(10, 3 + index as u16, &score, Color::White);
+        }
+
+        let rows = self.grid.rows;
+        self.grid.print(
+            0,
+            rows - 1,
+            &" ".repeat(self.grid.cols.into()),
+            Color::White,
+        );
+        self.grid
+            .print(0, rows - 1, "Enter your name:", Color::White);
+        self.render()?;
+
+        let mut name = String::new();
+        while events::read_name(&mut name)? {
+            self.grid.print(
+                0,
+                rows - 1,
+                &format!("Enter your name: {}", name),
+                Color::White,
+            );
+            self.render()?;
+        }
+
+        if !name.is_empty() {
+            scores.scores.push(scores::Score::new(name, points, pack));
+            scores.write(10);
+        }
+        Ok(())
+    }
+
+    // asks whether to play again after the rover ran out of lives
+    pub fn game_over(&mut self) -> io::Result<GameOverChoice> {
+        let mut menu = Menu::new(
+            &format!("Game over! You made {} points", self.ctx.world.rover.points),
+            vec![
+                ("Play again".to_string(), GameOverChoice::PlayAgain),
+                ("Main menu".to_string(), GameOverChoice::MainMenu),
+                ("Quit".to_string(), GameOverChoice::Quit),
+            ],
+        );
+        loop {
+            self.rasterize();
+            self.grid.dim();
+            menu.rasterize(&mut self.grid);
+            self.render()?;
+
+            for key in events::keys(Duration::from_millis(100))? {
+                if self.ctx.config.keys.action(&key) == Some(Action::Quit) {
+                    return Ok(GameOverChoice::Quit);
+                }
+                if let Some(choice) = menu.handle(&key) {
+                    return Ok(choice);
+                }
+            }
+        }
+    }
+
+    // starts a new frame that is as big as the terminal
+    fn blank(&mut self) -> io::Result<()> {
+        let (cols, rows) = self.renderer.size()?;
+        self.grid.resize(cols, rows);
+        self.grid.clear();
+        Ok(())
+    }
+
+    // shows the frame until any key is pressed
+    fn wait(&mut self) -> io::Result<()> {
+        self.render()?;
+        while events::keys(Duration::from_millis(100))?.is_empty() {}
+        Ok(())
+    }
+
+    pub fn title(&mut self) -> io::Result<TitleChoice> {
+        loop {
+            self.blank()?;
+            let width = LOGO.lines().map(str::len).max().unwrap_or_default() as u16;
+            let col = self.grid.cols.saturating_sub(width) / 2;
+            for (index, line) in LOGO.lines().enumerate() {
+                self.grid
+                    .print(col, index as u16, line, self.ctx.config.color_rover);
+            }
+            self.title.items[1].0 = format!("Mode: {:?}", self.ctx.mode);
+            let pack = &self.packs[self.pack];
+            self.title.items[2].0 = if pack.author.is_empty() {
+                format!("Levels: {}", pack.name)
+            } else {
+                format!("Levels: {} by {}", pack.name, pack.author)
+            };
+            self.title.rasterize(&mut self.grid);
+            self.render()?;
+
+            for key in events::keys(Duration::from_millis(100))? {
+                if self.ctx.config.keys.action(&key) == Some(Action::Quit) {
+                    return Ok(TitleChoice::Quit);
+                }
+                match self.title.handle(&key) {
+                    Some(TitleChoice::Mode) => self.ctx.mode = self.ctx.mode.next(),
+                    Some(TitleChoice::Pack) => self.select((self.pack + 1) % self.packs.len()),
+                    Some(choice) => return Ok(choice),
+                    None => (),
+                }
+            }
+        }
+    }
+
+    pub fn scoreboard(&mut self) -> io::Result<()> {
+        self.blank()?;
+        let scores = scores::Scores::read();
+        let title = format!("Scoreboard of the {} levels:", self.packs[self.pack].name);
+        self.grid.print(10, 2, &title, Color::White);
+        for (index, score) in scores.pack(self.pack_name().as_deref()).enumerate() {
+            let score: String = score.into();
+            self.grid.print(10, 3 + index as u16, &score, Color::White);
+        }
+        self.wait()
+    }
+
+    // lets the player change the colors, characters and keys while a preview
+    // shows how the game looks with them, and saves them on request
+    pub fn settings(&mut self) -> io::Result<()> {
+        // the levels of the last run may have random ones added
+        let mut original = self.ctx.config.clone();
+        original.levels = self.ctx.configured_levels.clone();
+        let seed = rand::random();
+        let mut preview = Context::new(seed, original.clone());
+        preview.mode = Mode::Practice;
+        // the last level has every kind of obstacle
+        preview.restart(seed, original.levels.len() - 1);
+
+        let mut items: Vec<(String, Setting)> = OPTIONS
+            .iter()
+            .filter_map(|(name, _)| {
+                if name.starts_with("color_") {
+                    Some((String::new(), Setting::Color(name)))
+                } else if name.starts_with("char_") {
+                    Some((String::new(), Setting::Char(name)))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (action, _) in original.keys.bindings() {
+            items.push((String::new(), Setting::Key(action)));
+        }
+        items.push(("Save".to_string(), Setting::Save));
+        items.push(("Back".to_string(), Setting::Back));
+        let mut menu = Menu::new("Settings", items);
+
+        let help = "Left and right change a color, enter edits, esc goes back";
+        let mut status = help.to_string();
+        let mut editing = false;
+        // like a game the preview moves on at the tick rate, while the keys
+        // are handled as soon as they come in
+        let mut next_tick = Instant::now();
+        loop {
+            let (cols, rows) = self.renderer.size()?;
+            let now = Instant::now();
+            if now >= next_tick {
+                next_tick = (next_tick + preview.config.tick()).max(now);
+                if cols >= World::MIN_COLS && rows >= World::MIN_ROWS {
+                    let actions = autopilot(&preview);
+                    preview.step(cols, rows, &actions);
+                }
+            }
+            self.grid.resize(cols, rows);
+            self.grid.clear();
+            preview.rasterize(&mut self.grid);
+            for (label, setting) in menu.items.iter_mut() {
+                if let Some(text) = setting.label(&mut preview.config) {
+                    *label = text;
+                }
+            }
+            menu.rasterize(&mut self.grid);
+            self.grid.print(0, 0, &status, Color::White);
+            self.render()?;
+
+            for key in events::keys(next_tick.saturating_duration_since(Instant::now()))? {
+                if editing {
+                    editing = false;
+                    status = help.to_string();
+                    if key.code == KeyCode::Esc {
+                        continue;
+                    }
+                    match menu.selected() {
+                        Setting::Char(name) => match key.code {
+                            KeyCode::Char(c) if !c.is_control() => {
+                                if let Some(ch) = preview.config.char_mut(name) {
+                                    *ch = c;
+                                }
+                            }
+                            _ => status = format!("{} is not a character", Key::from(key)),
+                        },
+                        Setting::Key(action) => match preview.config.keys.action(&key) {
+                            Some(other) if other != action => {
+                                status = format!(
+                                    "{} is already bound to {}",
+                                    Key::from(key),
+                                    format!("{:?}", other).to_lowercase()
+                                )
+                            }
+                            _ => *preview.config.keys.keys_mut(action) = vec![Key::from(key)],
+                        },
+                        _ => (),
+                    }
+                    continue;
+                }
+                let step = match key.code {
+                    KeyCode::Left => -1,
+                    KeyCode::Right => 1,
+                    _ => 0,
+                };
+                match (menu.selected(), step) {
+                    (Setting::Color(name), step) if step != 0 => {
+                        if let Some(color) = preview.config.color_mut(name) {
+                            *color = color.cycle(step);
+                        }
+                        continue;
+                    }
+                    _ => (),
+                }
+                if key.code == KeyCode::Esc {
+                    return Ok(());
+                }
+                match menu.handle(&key) {
+                    Some(Setting::Color(name)) => {
+                        if let Some(color) = preview.config.color_mut(name) {
+                            *color = color.cycle(1);
+                        }
+                    }
+                    Some(Setting::Char(_)) => {
+                        editing = true;
+                        status = "Type the new character, esc keeps the old one".to_string();
+                    }
+                    Some(Setting::Key(action)) => {
+                        editing = true;
+                        status = format!(
+                            "Press the new key to {}, esc keeps the old one",
+                            format!("{:?}", action).to_lowercase()
+                        );
+                    }
+                    Some(Setting::Save) => {
+                        // the preview adds random levels once it gets past
+                        // the last one, they are not part of the settings
+                        preview.config.levels = original.levels.clone();
+                        match preview.config.write(&original) {
+                            Ok(_) => {
+                                self.ctx.reload(preview.config);
+                                return Ok(());
+                            }
+                            Err(e) => status = format!("Could not save the settings: {}", e),
+                        }
+                    }
+                    Some(Setting::Back) => return Ok(()),
+                    None => (),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Setting {
+    Color(&'static str),
+    Char(&'static str),
+    Key(Action),
+    Save,
+    Back,
+}
+
+impl Setting {
+    // the text of the menu item, with the current value
+    fn label(self, config: &mut Config) -> Option<String> {
+        let describe = |name: &str| {
+            let (_, text) = OPTIONS.iter().find(|(option, _)| *option == name)?;
+            let mut chars = text.chars();
+            let first = chars.next()?.to_uppercase();
+            Some(format!("{}{}", first, chars.as_str()))
+        };
+        match self {
+            Setting::Color(name) => Some(format!(
+                "{:<34} {:?}",
+                describe(name)?,
+                config.color_mut(name)?
+            )),
+            Setting::Char(name) => Some(format!(
+                "{:<34} {}",
+                describe(name)?,
+                config.char_mut(name)?
+            )),
+            Setting::Key(action) => {
+                let keys: Vec<String> = config
+                    .keys
+                    .keys_mut(action)
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect();
+                Some(format!(
+                    "{:<34} {}",
+                    format!("Keys to {}", format!("{:?}", action).to_lowercase()),
+                    keys.join(", ")
+                ))
+            }
+            Setting::Save | Setting::Back => None,
+        }
+    }
+}
+
+// the rover of the settings preview jumps over the craters and shoots now
+// and then, so every part of the game can be seen
+fn autopilot(preview: &Context) -> Vec<Action> {
+    let world = &preview.world;
+    // the crater has to be just past the range it crashes the rover in
+    let end = world.rover.range().end;
+    let ahead = end..end + 2;
+    let mut actions = vec![];
+    // another jump while in the air would cut the jump short
+    if !world.rover.jumping()
+        && world
+            .craters
+            .iter()
+            .any(|crater| ahead.contains(&crater.col))
+    {
+        actions.push(Action::Jump);
+    }
+    if world.frame.is_multiple_of(8) {
+        actions.push(Action::Shoot);
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use marsrover::world::Event;
+
+    #[test]
+    fn the_autopilot_clears_the_craters() {
+        let config = Config::default();
+        let mut preview = Context::new(1, config.clone());
+        preview.mode = Mode::Practice;
+        preview.restart(1, config.levels.len() - 1);
+        let mut events = vec![];
+        for _ in 0..5000 {
+            let actions = autopilot(&preview);
+            events.extend(preview.step(100, 30, &actions));
+        }
+        assert!(events.contains(&Event::CraterCleared));
+        assert!(!events.contains(&Event::Crashed));
+    }
+}
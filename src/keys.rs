@@ -0,0 +1,302 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::context::Action;
+
+const NAMES: [(&str, KeyCode); 14] = [
+    ("Space", KeyCode::Char(' ')),
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Delete", KeyCode::Delete),
+];
+
+const MODIFIERS: [(&str, KeyModifiers); 3] = [
+    ("Ctrl", KeyModifiers::CONTROL),
+    ("Alt", KeyModifiers::ALT),
+    ("Shift", KeyModifiers::SHIFT),
+];
+
+// a key with its modifiers, written like `j`, `Space`, `Up` or `Ctrl+c`
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Key {
+            code,
+            modifiers: normalize(code, modifiers),
+        }
+    }
+
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        *self == Key::from(*event)
+    }
+}
+
+// the shift key is already part of characters like `J`
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> KeyModifiers {
+    let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+    match code {
+        KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+        _ => modifiers,
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        while let Some((prefix, key)) = rest.split_once('+') {
+            match MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(prefix))
+            {
+                Some((_, modifier)) if !key.is_empty() => {
+                    modifiers |= *modifier;
+                    rest = key;
+                }
+                _ => break,
+            }
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(rest))
+            {
+                Some((_, code)) => *code,
+                None => match rest.strip_prefix(['F', 'f']).map(str::parse) {
+                    Some(Ok(n @ 1..=12)) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key `{}`", s)),
+                },
+            },
+        };
+        Ok(Key::new(code, modifiers))
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, modifier) in MODIFIERS {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match NAMES.iter().find(|(_, code)| *code == self.code) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => match self.code {
+                KeyCode::Char(c) => write!(f, "{}", c),
+                KeyCode::F(n) => write!(f, "F{}", n),
+                code => write!(f, "{:?}", code),
+            },
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> String {
+        key.to_string()
+    }
+}
+
+// every action can be bound to a single key or to a list of keys
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Key>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    let names = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    };
+    names
+        .iter()
+        .map(|name| name.parse().map_err(de::Error::custom))
+        .collect()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct Keys {
+    #[serde(deserialize_with = "one_or_many")]
+    pub jump: Vec<Key>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub shoot: Vec<Key>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub quit: Vec<Key>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub pause: Vec<Key>,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        let key = |code| Key::new(code, KeyModifiers::NONE);
+        Keys {
+            jump: vec![key(KeyCode::Char(' '))],
+            shoot: vec![key(KeyCode::Char('j'))],
+            quit: vec![
+                key(KeyCode::Char('q')),
+                Key::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            ],
+            pause: vec![key(KeyCode::Char('p')), key(KeyCode::Esc)],
+        }
+    }
+}
+
+impl Keys {
+    pub fn bindings(&self) -> [(Action, &[Key]); 4] {
+        [
+            (Action::Jump, &self.jump),
+            (Action::Shoot, &self.shoot),
+            (Action::Quit, &self.quit),
+            (Action::Pause, &self.pause),
+        ]
+    }
+
+    pub fn keys_mut(&mut self, action: Action) -> &mut Vec<Key> {
+        match action {
+            Action::Jump => &mut self.jump,
+            Action::Shoot => &mut self.shoot,
+            Action::Quit => &mut self.quit,
+            Action::Pause => &mut self.pause,
+        }
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+        self.bindings()
+            .into_iter()
+            .find(|(_, keys)| keys.iter().any(|key| key.matches(event)))
+            .map(|(action, _)| action)
+    }
+
+    // a key must not be bound to more than one action
+    pub fn conflicts(&self) -> Vec<String> {
+        let bindings = self.bindings();
+        let mut conflicts = vec![];
+        for (index, (action, keys)) in bindings.iter().enumerate() {
+            for (other, otherkeys) in &bindings[index + 1..] {
+                for key in keys.iter().filter(|key| otherkeys.contains(key)) {
+                    conflicts.push(format!(
+                        "key `{}` is bound to both {} and {}",
+                        key,
+                        format!("{:?}", action).to_lowercase(),
+                        format!("{:?}", other).to_lowercase()
+                    ));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_characters_and_names() {
+        assert_eq!(key("j"), Key::new(KeyCode::Char('j'), KeyModifiers::NONE));
+        assert_eq!(key("+"), Key::new(KeyCode::Char('+'), KeyModifiers::NONE));
+        assert_eq!(
+            key("space"),
+            Key::new(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            key("PageDown"),
+            Key::new(KeyCode::PageDown, KeyModifiers::NONE)
+        );
+        assert_eq!(key("F12"), Key::new(KeyCode::F(12), KeyModifiers::NONE));
+        assert!("F13".parse::<Key>().is_err());
+        assert!("Foo".parse::<Key>().is_err());
+        assert!("Ctrl+".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        assert_eq!(
+            key("Ctrl+c"),
+            Key::new(KeyCode::Char('c'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            key("alt+shift+Up"),
+            Key::new(KeyCode::Up, KeyModifiers::ALT | KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            key("Ctrl++"),
+            Key::new(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn shift_is_part_of_characters() {
+        assert_eq!(key("Shift+j"), key("j"));
+        let event = KeyEvent::new(KeyCode::Char('J'), KeyModifiers::SHIFT);
+        assert!(key("J").matches(&event));
+        assert!(!key("j").matches(&event));
+        let event = KeyEvent::new(KeyCode::Tab, KeyModifiers::SHIFT);
+        assert!(key("Shift+Tab").matches(&event));
+        assert!(!key("Tab").matches(&event));
+    }
+
+    #[test]
+    fn displays_what_it_parses() {
+        for name in ["j", "Space", "Ctrl+c", "Alt+Shift+Up", "F5", "Esc"] {
+            assert_eq!(key(name).to_string(), name);
+        }
+    }
+
+    #[test]
+    fn finds_conflicts() {
+        let mut keys = Keys::default();
+        assert!(keys.conflicts().is_empty());
+        keys.jump.push(key("q"));
+        assert_eq!(
+            keys.conflicts(),
+            vec!["key `q` is bound to both jump and quit".to_string()]
+        );
+    }
+}
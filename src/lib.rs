@@ -0,0 +1,16 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! The simulation, configuration and scoreboard of `marsrover`, usable
+//! without the terminal frontend of the `marsrover` binary.
+
+pub mod config;
+pub mod context;
+pub mod keys;
+pub mod menu;
+pub mod pack;
+pub mod render;
+pub mod replay;
+pub mod scores;
+pub mod world;
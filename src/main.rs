@@ -2,224 +2,219 @@
 //
 // SPDX-License-Identifier: MIT
 
-use crossterm::{
-    cursor, queue,
-    style::{self, Stylize},
-    terminal,
-    terminal::size,
-    ExecutableCommand,
-};
-use std::io::{self, Write};
+use clap::error::ErrorKind;
+use std::io;
 
-mod config;
-mod context;
+mod cli;
 mod events;
-mod scores;
-mod world;
-
-use context::Context;
-
-fn draw(
-    mut stdout: &std::io::Stdout,
-    col: u16,
-    row: u16,
-    text: String,
-    color: config::Color,
-) -> io::Result<()> {
-    queue!(
-        stdout,
-        cursor::MoveTo(col, row),
-        style::PrintStyledContent(text.with(color.into()))
-    )?;
-    Ok(())
-}
+mod game;
+mod session;
+
+use cli::{Cli, Command, ConfigCommand};
+use game::{Game, GameOverChoice, TitleChoice};
+use marsrover::config::{Config, Diagnostic, Watch};
+use marsrover::context::State;
+use marsrover::pack::Pack;
+use marsrover::render::{CrosstermRenderer, Renderer};
+use marsrover::replay::Replay;
+use marsrover::scores;
+use session::Session;
 
 fn main() -> io::Result<()> {
-    let mut ctx = Context::default();
-
-    // if user requests the scoreboard using the `-s` switch,
-    // just print that and exit gracefully
-    if let Some(arg) = std::env::args().nth(1) {
-        if arg.eq("-s") {
+    let cli = Cli::parse_args();
+    let config = Config::read(cli.config.as_deref(), &cli.set);
+
+    let command = if cli.scores {
+        Some(Command::Scores)
+    } else {
+        cli.command
+    };
+    let args = match command {
+        // if user requests the scoreboard, just print that and exit gracefully
+        Some(Command::Scores) => {
             let scores: String = scores::Scores::read().into();
             println!("Marsrover-Scores\n{}", scores);
-            std::process::exit(0);
+            return Ok(());
         }
-    }
-
-    terminal::enable_raw_mode()?;
-    let mut stdout = io::stdout();
-
-    stdout.execute(terminal::Clear(terminal::ClearType::All))?;
-    stdout.execute(cursor::Hide)?;
-
-    while ctx.run() {
-        let (cols, rows) = size()?;
-        ctx.world.update(cols, rows, &ctx.config.levels[ctx.level]);
-
-        events::events(&mut ctx)?;
-
-        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
-
-        draw(
-            &stdout,
-            0,
-            rows - 1,
-            format!("{}", ctx),
-            ctx.config.color_context,
-        )?;
-
-        /* calculate actions */
-        if ctx.world.rover.moving() {
-            if ctx
-                .world
-                .craters
-                .iter()
-                .any(|crater| crater.col == ctx.world.rover.col)
-            {
-                ctx.world.rover.points += 4;
+        Some(Command::Config {
+            command: Some(ConfigCommand::Check),
+        }) => {
+            // the problems of the configuration and of the packs are all
+            // reported before giving up
+            let (config, mut diagnostics) = match config {
+                Ok(config) => (Some(config), vec![]),
+                Err(diagnostics) => (None, diagnostics),
+            };
+            if let Err(problems) = Pack::all() {
+                diagnostics.extend(problems);
             }
-            if ctx
-                .world
-                .craters
-                .iter()
-                .any(|crater| ctx.world.rover.range().contains(&crater.col))
-                && !ctx.world.rover.jumping()
-            {
-                ctx.world.rover.crash();
+            if !diagnostics.is_empty() {
+                report(&diagnostics);
+                std::process::exit(1);
             }
-            if ctx
-                .world
-                .monsters
-                .iter()
-                .any(|monster| monster.col == ctx.world.rover.col + 5)
-            {
-                ctx.world.rover.monstercrash();
+            match config {
+                Some(config) if config.sources.is_empty() => {
+                    println!("No configuration file, using the defaults")
+                }
+                Some(config) => {
+                    for source in &config.sources {
+                        println!("{}: ok", source);
+                    }
+                }
+                None => (),
             }
+            return Ok(());
         }
-
-        for (row, line) in ctx.worldtolines().iter().enumerate() {
-            // only draw if there is actually anything to draw
-            if line.chars().any(|c| c != ' ') {
-                draw(
-                    &stdout,
-                    0,
-                    row as u16,
-                    line.to_string(),
-                    config::Color::White,
-                )?;
+        Some(Command::Config {
+            command: Some(ConfigCommand::Default { write }),
+        }) => {
+            if !write {
+                print!("{}", Config::commented());
+                return Ok(());
             }
-        }
-
-        /* draw the rover */
-        let bstr: String = ctx.world.rover.into();
-        for (index, line) in bstr.lines().rev().enumerate() {
-            draw(
-                &stdout,
-                ctx.world.rover.col,
-                ctx.world.rover.row() - index as u16,
-                line.to_string(),
-                ctx.config.color_rover,
-            )?;
-        }
-        ctx.world.rover.tick();
-
-        // sum up the points of all levels up to now...
-        let points: u16 = ctx.config.levels[0..=ctx.level]
-            .iter()
-            .map(|x| x.points)
-            .sum();
-        if points <= ctx.world.rover.points {
-            if ctx.level < ctx.config.levels.len() - 1 {
-                ctx.level += 1;
-                ctx.addmessage(format!("Level up! You're now on level {}", ctx.level), 40);
-                ctx.addmessage(ctx.config.levels[ctx.level].desc.to_string(), 40);
-            } else {
-                ctx.config.levels.push(config::Level::random());
+            match Config::write_default(cli.config.as_deref()) {
+                Ok(path) => println!("Wrote the default configuration to {}", path.display()),
+                Err(e) => {
+                    eprintln!("Could not write the default configuration: {}", e);
+                    std::process::exit(1);
+                }
             }
+            return Ok(());
         }
-
-        // print messages, if any
-        ctx.messages.retain(|message| message.tick > 0);
-        for (index, message) in ctx.messages.iter_mut().rev().enumerate() {
-            message.tick -= 1;
-            let pos: u16 = rows - 20 - index as u16;
-            if message.tick > 0 {
-                draw(
-                    &stdout,
-                    20,
-                    pos,
-                    message.message.to_string(),
-                    config::Color::White,
-                )?;
+        Some(Command::Config { command: None }) => {
+            let config = valid(config);
+            if config.sources.is_empty() {
+                println!("# no configuration file, these are the defaults");
             }
+            for source in &config.sources {
+                println!("# read from {}", source);
+            }
+            match toml::to_string(&config) {
+                Ok(content) => print!("{}", content),
+                Err(e) => {
+                    eprintln!("Could not print the configuration: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
         }
-
-        if ctx.world.rover.rebooting() {
-            ctx.world.reset();
-        }
-
-        stdout.flush()?;
+        Some(Command::Play(_)) | None => cli.play,
+    };
+    let config = valid(config);
+    let packs = Pack::all().unwrap_or_else(|diagnostics| {
+        report(&diagnostics);
+        eprintln!("Please fix the level packs, `marsrover config check` checks them again.");
+        std::process::exit(1);
+    });
+    let packs: Vec<Pack> = std::iter::once(Pack::configured(&config.levels))
+        .chain(packs)
+        .collect();
+
+    let playback = match &args.replay {
+        Some(path) => match Replay::read(path) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                eprintln!("Could not read replay file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // a replay is played with the pack it was recorded with, unless it
+    // brings its levels along
+    let name = match &playback {
+        Some(replay) if !replay.levels.is_empty() => None,
+        Some(replay) => replay.pack.as_deref(),
+        None => args.pack.as_deref(),
+    };
+    let pack = match name {
+        Some(name) => match packs.iter().skip(1).position(|pack| pack.name == name) {
+            Some(index) => index + 1,
+            None => cli::error(
+                ErrorKind::InvalidValue,
+                &format!("there is no level pack called `{}`", name),
+            ),
+        },
+        None => 0,
+    };
+    let levels = packs[pack].levels.len();
+    if args.level >= levels {
+        cli::error(
+            ErrorKind::ValueValidation,
+            &format!(
+                "--level has to be lower than the number of levels, which is {}",
+                levels
+            ),
+        );
     }
 
-    if ctx.world.rover.points > 0 {
-        let mut scores = scores::Scores::read();
+    let watch = Watch::new(&config, cli.config.as_deref(), &cli.set);
+    let renderer = CrosstermRenderer::default();
+    let mut game = Game::new(renderer, config, packs, pack, args, playback, watch);
 
-        draw(
-            &stdout,
-            10,
-            2,
-            "Scoreboard:".to_string(),
-            config::Color::White,
-        )?;
-        for (index, score) in scores.scores.iter().enumerate() {
-            draw(
-                &stdout,
-                10,
-                3 + index as u16,
-                score.into(),
-                config::Color::White,
-            )?;
-        }
-        stdout.flush()?;
+    let session = Session::enter()?;
+    let result = run(&mut game);
+    drop(session);
 
-        let (_cols, rows) = size()?;
-        queue!(
-            stdout,
-            cursor::MoveTo(0, rows - 1),
-            terminal::Clear(terminal::ClearType::CurrentLine)
-        )?;
+    if let Some(signal) = session::terminated() {
+        std::process::exit(128 + signal);
+    }
+    result?;
 
-        draw(
-            &stdout,
-            0,
-            rows - 1,
-            "Enter your name:".to_string(),
-            config::Color::White,
-        )?;
-        stdout.flush()?;
+    if let Some(replay) = &game.playback {
+        println!(
+            "Replay finished with {} points, the recorded run had {} points",
+            game.ctx.world.rover.points, replay.points
+        );
+    }
+    for error in &game.errors {
+        eprintln!("{}", error);
+    }
 
-        let mut name = String::new();
-        while events::read_name(&mut name) {
-            draw(
-                &stdout,
-                0,
-                rows - 1,
-                format!("Enter your name: {}", name),
-                config::Color::White,
-            )?;
-            stdout.flush()?;
-        }
+    Ok(())
+}
 
-        if !name.is_empty() {
-            scores
-                .scores
-                .push(scores::Score::new(name, ctx.world.rover.points));
-            scores.write(10);
-        }
+fn report(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
     }
+}
 
-    terminal::disable_raw_mode()?;
+// the game does not start with a broken configuration, the problems would be
+// hidden by the game screen and it could crash on invalid values
+fn valid(config: Result<Config, Vec<Diagnostic>>) -> Config {
+    config.unwrap_or_else(|diagnostics| {
+        report(&diagnostics);
+        eprintln!("Please fix the configuration file, `marsrover config check` checks it again.");
+        std::process::exit(1);
+    })
+}
 
+fn run<R: Renderer>(game: &mut Game<R>) -> io::Result<()> {
+    if game.playback.is_some() {
+        game.play()?;
+        return game.finish();
+    }
+    'title: loop {
+        match game.title()? {
+            TitleChoice::Start => loop {
+                game.restart();
+                game.play()?;
+                game.finish()?;
+                // quitting a run leads back to the title screen
+                if game.ctx.state == State::Quit {
+                    break;
+                }
+                match game.game_over()? {
+                    GameOverChoice::PlayAgain => (),
+                    GameOverChoice::MainMenu => break,
+                    GameOverChoice::Quit => break 'title,
+                }
+            },
+            TitleChoice::Scores => game.scoreboard()?,
+            TitleChoice::Settings => game.settings()?,
+            TitleChoice::Mode | TitleChoice::Pack | TitleChoice::Quit => break,
+        }
+    }
     Ok(())
 }
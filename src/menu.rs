@@ -0,0 +1,86 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::config::Color;
+use crate::render::{Cell, Grid};
+
+pub struct Menu<T> {
+    pub title: String,
+    pub items: Vec<(String, T)>,
+    pub selected: usize,
+}
+
+impl<T: Copy> Menu<T> {
+    pub fn new(title: &str, items: Vec<(String, T)>) -> Self {
+        Menu {
+            title: title.to_string(),
+            items,
+            selected: 0,
+        }
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.checked_sub(1).unwrap_or(self.items.len() - 1);
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1) % self.items.len();
+    }
+
+    pub fn selected(&self) -> T {
+        self.items[self.selected].1
+    }
+
+    // moves the selection with the arrow keys and returns the selected item
+    // once it is chosen with enter
+    pub fn handle(&mut self, key: &KeyEvent) -> Option<T> {
+        match key.code {
+            KeyCode::Up => self.up(),
+            KeyCode::Down | KeyCode::Tab => self.down(),
+            KeyCode::Enter => return Some(self.selected()),
+            _ => (),
+        }
+        None
+    }
+
+    // draws the menu as a box in the middle of the grid
+    pub fn rasterize(&self, grid: &mut Grid) {
+        let width = self
+            .items
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .chain(Some(self.title.chars().count()))
+            .max()
+            .unwrap_or_default() as u16
+            + 4;
+        let height = self.items.len() as u16 + 2;
+        let col = grid.cols.saturating_sub(width) / 2;
+        let row = grid.rows.saturating_sub(height) / 2;
+
+        let line = |text: &str| format!("  {:width$}", text, width = usize::from(width) - 2);
+        grid.print(col, row, &line(&self.title), Color::White);
+        grid.print(col, row + 1, &line(""), Color::White);
+        for (index, (label, _)) in self.items.iter().enumerate() {
+            let row = row + 2 + index as u16;
+            grid.print(col, row, &line(label), Color::White);
+            if index == self.selected {
+                for col in col..col + width {
+                    if let Some(cell) = grid.get(col, row).copied() {
+                        grid.set(
+                            col,
+                            row,
+                            Cell {
+                                fg: Color::Black,
+                                bg: Some(Color::White),
+                                ..cell
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+extern crate xdg;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{self, Diagnostic, Level};
+
+// a set of levels in its own file, to play instead of the levels of the
+// configuration
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Pack {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    pub levels: Vec<Level>,
+}
+
+impl Pack {
+    // the levels of the configuration file, which are always there to choose
+    pub fn configured(levels: &[Level]) -> Self {
+        Pack {
+            name: "Default".to_string(),
+            author: String::new(),
+            levels: levels.to_vec(),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Pack, Vec<Diagnostic>> {
+        let source = path.display().to_string();
+        let diagnostic = |line: Option<usize>, message: String| Diagnostic {
+            source: source.clone(),
+            line,
+            message,
+        };
+        let content = fs::read_to_string(path)
+            .map_err(|e| vec![diagnostic(None, format!("could not read file: {}", e))])?;
+        let pack: Pack = toml::from_str(&content).map_err(|e| {
+            let line = e.span().map(|span| config::line(&content, span.start));
+            vec![diagnostic(line, e.message().trim().to_string())]
+        })?;
+        let value: toml::Value = toml::from_str(&content).expect("the pack was just parsed");
+        let known = toml::Value::try_from(&pack).expect("a pack can be serialized");
+        let unknown = config::unknown(&value, &known, String::new());
+        if !unknown.is_empty() {
+            let mut diagnostics: Vec<Diagnostic> = unknown
+                .into_iter()
+                .map(|key| {
+                    let message = format!("there is no option `{}`", key);
+                    diagnostic(config::locate(&content, &key), message)
+                })
+                .collect();
+            diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+            return Err(diagnostics);
+        }
+        let problems = config::validate_levels(&pack.levels);
+        if !problems.is_empty() {
+            return Err(problems
+                .into_iter()
+                .map(|problem| diagnostic(config::locate(&content, &problem.key), problem.message))
+                .collect());
+        }
+        Ok(pack)
+    }
+
+    // the packs in `$XDG_DATA_HOME/marsrover/levels` and the system wide data
+    // directories, ordered by their file names
+    pub fn all() -> Result<Vec<Pack>, Vec<Diagnostic>> {
+        let Ok(xdg) = xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME")) else {
+            return Ok(vec![]);
+        };
+        let mut paths: Vec<_> = xdg
+            .list_data_files("levels")
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "toml")
+            })
+            .collect();
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+        let mut packs = vec![];
+        let mut diagnostics = vec![];
+        for path in paths {
+            match Pack::read(&path) {
+                Ok(pack) => packs.push(pack),
+                Err(problems) => diagnostics.extend(problems),
+            }
+        }
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        Ok(packs)
+    }
+}
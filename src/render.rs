@@ -0,0 +1,231 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use crossterm::{cursor, queue, style, terminal};
+use std::io::{self, Write};
+
+use crate::config::Color;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    // `None` keeps the background of the terminal
+    pub bg: Option<Color>,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            ch: ' ',
+            fg: Color::White,
+            bg: None,
+        }
+    }
+}
+
+// one frame, the world is rasterized into it and the renderers put it on
+// the screen
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Grid {
+    pub cols: u16,
+    pub rows: u16,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Grid {
+            cols,
+            rows,
+            cells: vec![Cell::default(); usize::from(cols) * usize::from(rows)],
+        }
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        if (cols, rows) != (self.cols, self.rows) {
+            *self = Grid::new(cols, rows);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    // greys out the whole frame, e.g. behind a menu
+    pub fn dim(&mut self) {
+        self.cells
+            .iter_mut()
+            .for_each(|cell| cell.fg = Color::DarkGrey);
+    }
+
+    fn index(&self, col: u16, row: u16) -> Option<usize> {
+        (col < self.cols && row < self.rows)
+            .then(|| usize::from(row) * usize::from(self.cols) + usize::from(col))
+    }
+
+    pub fn get(&self, col: u16, row: u16) -> Option<&Cell> {
+        self.index(col, row).map(|index| &self.cells[index])
+    }
+
+    pub fn set(&mut self, col: u16, row: u16, cell: Cell) {
+        if let Some(index) = self.index(col, row) {
+            self.cells[index] = cell;
+        }
+    }
+
+    // text that does not fit into the grid is cut off
+    pub fn print(&mut self, col: u16, row: u16, text: &str, fg: Color) {
+        if let Some(start) = self.index(col, row) {
+            let end = start + usize::from(self.cols - col);
+            for (cell, ch) in self.cells[start..end].iter_mut().zip(text.chars()) {
+                *cell = Cell { ch, fg, bg: None };
+            }
+        }
+    }
+
+    pub fn line(&self, row: u16) -> String {
+        (0..self.cols)
+            .filter_map(|col| self.get(col, row))
+            .map(|cell| cell.ch)
+            .collect()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.rows).map(|row| self.line(row)).collect()
+    }
+}
+
+pub trait Renderer {
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn render(&mut self, grid: &Grid) -> io::Result<()>;
+    // forgets what is on the screen, so the next frame is drawn completely,
+    // e.g. after the terminal was handed to another program
+    fn invalidate(&mut self) {}
+}
+
+// keeps what is on the screen, so only the cells that changed between two
+// frames have to be written out
+pub struct CrosstermRenderer<W: Write> {
+    out: W,
+    front: Grid,
+}
+
+impl Default for CrosstermRenderer<io::Stdout> {
+    fn default() -> Self {
+        CrosstermRenderer::new(io::stdout())
+    }
+}
+
+impl<W: Write> CrosstermRenderer<W> {
+    pub fn new(out: W) -> Self {
+        let (cols, rows) = terminal::size().unwrap_or_default();
+        CrosstermRenderer {
+            out,
+            front: Grid::new(cols, rows),
+        }
+    }
+}
+
+impl<W: Write> Renderer for CrosstermRenderer<W> {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn invalidate(&mut self) {
+        self.front = Grid::default();
+    }
+
+    fn render(&mut self, grid: &Grid) -> io::Result<()> {
+        // if the terminal was resized the whole screen has to be redrawn
+        if (grid.cols, grid.rows) != (self.front.cols, self.front.rows) {
+            self.front = Grid::new(grid.cols, grid.rows);
+            queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+        }
+
+        let mut cursor: Option<(u16, u16)> = None;
+        let mut fg: Option<Color> = None;
+        let mut bg: Option<Option<Color>> = None;
+        let changed = grid
+            .cells
+            .iter()
+            .zip(self.front.cells.iter())
+            .enumerate()
+            .filter(|(_, (new, old))| new != old);
+        for (index, (cell, _)) in changed {
+            let col = (index % usize::from(grid.cols)) as u16;
+            let row = (index / usize::from(grid.cols)) as u16;
+            if cursor != Some((col, row)) {
+                queue!(self.out, cursor::MoveTo(col, row))?;
+            }
+            if fg != Some(cell.fg) {
+                queue!(self.out, style::SetForegroundColor(cell.fg.into()))?;
+                fg = Some(cell.fg);
+            }
+            if bg != Some(cell.bg) {
+                let color = cell.bg.map_or(style::Color::Reset, |color| color.into());
+                queue!(self.out, style::SetBackgroundColor(color))?;
+                bg = Some(cell.bg);
+            }
+            queue!(self.out, style::Print(cell.ch))?;
+            cursor = Some((col + 1, row));
+        }
+        queue!(self.out, style::ResetColor)?;
+        self.front.clone_from(grid);
+        self.out.flush()
+    }
+}
+
+// keeps the last rendered frame, e.g. to look at it in tests
+pub struct MemoryRenderer {
+    pub cols: u16,
+    pub rows: u16,
+    pub frame: Grid,
+}
+
+impl MemoryRenderer {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        MemoryRenderer {
+            cols,
+            rows,
+            frame: Grid::new(cols, rows),
+        }
+    }
+}
+
+impl Renderer for MemoryRenderer {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok((self.cols, self.rows))
+    }
+
+    fn render(&mut self, grid: &Grid) -> io::Result<()> {
+        self.frame.clone_from(grid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::context::Context;
+
+    #[test]
+    fn renders_a_frame_into_memory() {
+        let mut ctx = Context::new(1, Config::default());
+        ctx.step(60, 25, &[]);
+        let mut renderer = MemoryRenderer::new(60, 25);
+        let (cols, rows) = renderer.size().unwrap();
+        let mut grid = Grid::new(cols, rows);
+        ctx.rasterize(&mut grid);
+        renderer.render(&grid).unwrap();
+
+        let lines = renderer.frame.lines();
+        assert_eq!(lines.len(), 25);
+        assert!(lines[24].starts_with("Lives: 3 Points: 0, Level: 0/5, Seed: 1"));
+        assert_eq!(lines[23], "#".repeat(60));
+        assert_eq!(lines[22], "#".repeat(60));
+        assert!(lines[20].contains("mm0"));
+    }
+}
@@ -0,0 +1,251 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::Level;
+use crate::context::{Action, Mode};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct Input {
+    pub frame: u64,
+    pub action: Action,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct Resize {
+    pub frame: u64,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct Replay {
+    // toml integers are signed, so the seed is stored as a string
+    #[serde(with = "seed")]
+    pub seed: u64,
+    #[serde(default)]
+    pub mode: Mode,
+    // the level the run started at
+    #[serde(default)]
+    pub level: usize,
+    // the level pack that was played, none for the configured levels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
+    pub points: u16,
+    // the levels the run was played with, older replays are played with the
+    // levels of the configuration
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub levels: Vec<Level>,
+    pub resizes: Vec<Resize>,
+    pub inputs: Vec<Input>,
+}
+
+impl Replay {
+    pub fn new(
+        seed: u64,
+        mode: Mode,
+        level: usize,
+        pack: Option<String>,
+        levels: Vec<Level>,
+    ) -> Self {
+        Replay {
+            seed,
+            mode,
+            level,
+            pack,
+            levels,
+            ..Default::default()
+        }
+    }
+
+    pub fn read(path: &Path) -> io::Result<Replay> {
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let content =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, content)
+    }
+
+    pub fn record(&mut self, frame: u64, action: Action) {
+        self.inputs.push(Input { frame, action });
+    }
+
+    // the world depends on the terminal size, so every change is recorded;
+    // a resize drops what is past the new edge, so several changes before
+    // one frame are all kept, in order
+    pub fn record_size(&mut self, frame: u64, cols: u16, rows: u16) {
+        if self.size(frame) != Some((cols, rows)) {
+            self.resizes.push(Resize { frame, cols, rows });
+        }
+    }
+
+    // the sizes the world got right before this frame, in order
+    pub fn resizes(&self, frame: u64) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.resizes
+            .iter()
+            .filter(move |resize| resize.frame == frame)
+            .map(|resize| (resize.cols, resize.rows))
+    }
+
+    pub fn actions(&self, frame: u64) -> impl Iterator<Item = Action> + '_ {
+        self.inputs
+            .iter()
+            .filter(move |input| input.frame == frame)
+            .map(|input| input.action)
+    }
+
+    pub fn size(&self, frame: u64) -> Option<(u16, u16)> {
+        self.resizes
+            .iter()
+            .rev()
+            .find(|resize| resize.frame <= frame)
+            .map(|resize| (resize.cols, resize.rows))
+    }
+}
+
+mod seed {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&seed.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::context::Context;
+
+    #[test]
+    fn keeps_seeds_toml_can_not_hold() {
+        let replay = Replay::new(u64::MAX, Mode::Practice, 2, None, vec![]);
+        let content = toml::to_string(&replay).unwrap();
+        assert!(content.contains(&format!("seed = \"{}\"", u64::MAX)));
+        let read: Replay = toml::from_str(&content).unwrap();
+        assert_eq!(read.seed, u64::MAX);
+        assert_eq!(read.mode, Mode::Practice);
+        assert_eq!(read.level, 2);
+        assert!(
+            toml::from_str::<Replay>("seed = \"x\"\npoints = 0\nresizes = []\ninputs = []")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn records_sizes_only_when_they_change() {
+        let mut replay = Replay::default();
+        assert_eq!(replay.size(0), None);
+        replay.record_size(0, 80, 24);
+        replay.record_size(1, 80, 24);
+        replay.record_size(5, 100, 30);
+        replay.record_size(6, 100, 30);
+        assert_eq!(replay.resizes.len(), 2);
+        assert_eq!(replay.size(0), Some((80, 24)));
+        assert_eq!(replay.size(4), Some((80, 24)));
+        assert_eq!(replay.size(5), Some((100, 30)));
+        assert_eq!(replay.size(1000), Some((100, 30)));
+
+        // going back and forth before one frame is not the same as staying
+        replay.record_size(7, 60, 25);
+        replay.record_size(7, 100, 30);
+        assert_eq!(
+            replay.resizes(7).collect::<Vec<_>>(),
+            vec![(60, 25), (100, 30)]
+        );
+        assert_eq!(replay.size(7), Some((100, 30)));
+        assert_eq!(replay.resizes(8).count(), 0);
+    }
+
+    #[test]
+    fn finds_the_actions_of_a_frame() {
+        let mut replay = Replay::default();
+        replay.record(3, Action::Jump);
+        replay.record(3, Action::Shoot);
+        replay.record(4, Action::Shoot);
+        assert_eq!(
+            replay.actions(3).collect::<Vec<_>>(),
+            vec![Action::Jump, Action::Shoot]
+        );
+        assert_eq!(replay.actions(4).collect::<Vec<_>>(), vec![Action::Shoot]);
+        assert_eq!(replay.actions(5).count(), 0);
+    }
+
+    #[test]
+    fn plays_back_what_was_recorded() {
+        let seed = 42;
+        let config = Config::default();
+        let mut ctx = Context::new(seed, config.clone());
+        ctx.mode = Mode::Practice;
+        ctx.restart(seed, 0);
+        let mut recording = Replay::new(seed, ctx.mode, 0, None, ctx.configured_levels.clone());
+        for frame in 0..3000 {
+            let (cols, rows) = if frame < 1000 { (100, 30) } else { (60, 25) };
+            // the window is dragged smaller and back while the game is paused
+            if frame == 500 {
+                for (cols, rows) in [(50, 20), (100, 30)] {
+                    ctx.world.resize(cols, rows);
+                    recording.record_size(ctx.world.frame, cols, rows);
+                }
+            }
+            let mut actions = vec![];
+            let rover = &ctx.world.rover;
+            let ahead = rover.range().end..rover.range().end + 2;
+            if !rover.jumping() && ctx.world.craters.iter().any(|c| ahead.contains(&c.col)) {
+                actions.push(Action::Jump);
+            }
+            if frame % 5 == 0 {
+                actions.push(Action::Shoot);
+            }
+            recording.record_size(ctx.world.frame, cols, rows);
+            for action in &actions {
+                recording.record(ctx.world.frame, *action);
+            }
+            ctx.step(cols, rows, &actions);
+        }
+        recording.points = ctx.world.rover.points;
+        assert!(recording.points > 0);
+        assert!(ctx.level > 0);
+
+        let path =
+            std::env::temp_dir().join(format!("marsrover-replay-{}.toml", std::process::id()));
+        recording.write(&path).unwrap();
+        let replay = Replay::read(&path);
+        let _ = fs::remove_file(&path);
+        let replay = replay.unwrap();
+
+        // the levels come from the replay, not from the configuration
+        let mut config = config;
+        config.levels.truncate(1);
+        let mut playback = Context::new(replay.seed, config);
+        playback.mode = replay.mode;
+        playback.set_levels(replay.levels.clone());
+        playback.restart(replay.seed, replay.level);
+        while playback.world.frame < ctx.world.frame {
+            let frame = playback.world.frame;
+            for (cols, rows) in replay.resizes(frame) {
+                playback.world.resize(cols, rows);
+            }
+            let (cols, rows) = replay.size(frame).unwrap();
+            let actions: Vec<Action> = replay.actions(frame).collect();
+            playback.step(cols, rows, &actions);
+        }
+        assert_eq!(playback.world.rover.points, replay.points);
+        assert_eq!(playback.level, ctx.level);
+        assert_eq!(playback.world.rover.lives, ctx.world.rover.lives);
+    }
+}
@@ -5,6 +5,7 @@
 extern crate chrono;
 extern crate xdg;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
@@ -15,14 +16,18 @@ pub struct Score {
     pub name: String,
     pub points: u16,
     pub timestamp: SystemTime,
+    // the level pack the points were made in, none for the configured levels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
 }
 
 impl Score {
-    pub fn new(name: String, points: u16) -> Self {
+    pub fn new(name: String, points: u16, pack: Option<String>) -> Self {
         Score {
             name,
             points,
             timestamp: SystemTime::now(),
+            pack,
         }
     }
 }
@@ -44,10 +49,25 @@ pub struct Scores {
     pub scores: Vec<Score>,
 }
 
+// the scores of the configured levels, followed by the ones of every pack
 impl From<Scores> for String {
     fn from(scores: Scores) -> String {
-        let scores: Vec<String> = scores.scores.iter().map(|score| score.into()).collect();
-        scores.join("\n")
+        let mut lines: Vec<String> = scores.pack(None).map(|score| score.into()).collect();
+        let mut packs: Vec<&str> = scores
+            .scores
+            .iter()
+            .filter_map(|score| score.pack.as_deref())
+            .collect();
+        packs.sort();
+        packs.dedup();
+        for pack in packs {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(format!("{}:", pack));
+            lines.extend(scores.pack(Some(pack)).map(String::from));
+        }
+        lines.join("\n")
     }
 }
 
@@ -65,10 +85,22 @@ impl Scores {
         Scores { scores: vec![] }
     }
 
+    pub fn pack<'a>(&'a self, pack: Option<&'a str>) -> impl Iterator<Item = &'a Score> {
+        self.scores
+            .iter()
+            .filter(move |score| score.pack.as_deref() == pack)
+    }
+
+    // keeps the best `max` scores of every pack
     pub fn write(&mut self, max: usize) -> bool {
         self.scores.sort_by_key(|score| score.points);
         self.scores.reverse();
-        self.scores.truncate(max);
+        let mut kept: HashMap<Option<String>, usize> = HashMap::new();
+        self.scores.retain(|score| {
+            let count = kept.entry(score.pack.clone()).or_default();
+            *count += 1;
+            *count <= max
+        });
 
         if let Ok(xdg_dirs) = xdg::BaseDirectories::with_prefix(env!("CARGO_CRATE_NAME")) {
             if let Ok(scores_path) = xdg_dirs.place_state_file("scores.toml") {
@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use crossterm::event::{
+    DisableFocusChange, EnableFocusChange, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, terminal};
+use std::io;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+// the state of the terminal is global, so it can be restored from the panic
+// hook and the signal handling thread as well
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static ENHANCED: AtomicBool = AtomicBool::new(false);
+static RESUMED: AtomicBool = AtomicBool::new(false);
+static TERMINATED: AtomicI32 = AtomicI32::new(0);
+
+// puts the terminal into the state the game needs: raw mode, alternate
+// screen, hidden cursor and focus reporting; restores it again when dropped
+pub struct Session;
+
+impl Session {
+    pub fn enter() -> io::Result<Session> {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = restore();
+            hook(info);
+        }));
+        #[cfg(unix)]
+        signals()?;
+
+        setup()?;
+        // terminals that support the keyboard enhancement protocol report key
+        // releases and repeats, which lets us tell a held key from a pressed
+        // one; terminals that do not answer the query at all are treated as
+        // unsupported
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            ENHANCED.store(true, Ordering::SeqCst);
+            enhance()?;
+        }
+        Ok(Session)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = restore();
+    }
+}
+
+fn enhance() -> io::Result<()> {
+    execute!(
+        io::stdout(),
+        PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+        )
+    )
+}
+
+fn setup() -> io::Result<()> {
+    if ACTIVE.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    terminal::enable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        terminal::EnterAlternateScreen,
+        cursor::Hide,
+        EnableFocusChange
+    )?;
+    if ENHANCED.load(Ordering::SeqCst) {
+        enhance()?;
+    }
+    RESUMED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+fn restore() -> io::Result<()> {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    if ENHANCED.load(Ordering::SeqCst) {
+        execute!(stdout, PopKeyboardEnhancementFlags)?;
+    }
+    execute!(
+        stdout,
+        DisableFocusChange,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    )?;
+    terminal::disable_raw_mode()
+}
+
+// hands the terminal back to the shell and stops the process, like Ctrl-Z
+// does outside of raw mode
+pub fn suspend() -> io::Result<()> {
+    restore()?;
+    #[cfg(unix)]
+    signal_hook::low_level::raise(signal_hook::consts::SIGSTOP)?;
+    setup()
+}
+
+// whether the terminal was set up again since the last call, which means
+// the screen has to be redrawn completely
+pub fn resumed() -> bool {
+    RESUMED.swap(false, Ordering::SeqCst)
+}
+
+// the signal that asked the game to terminate, if any
+pub fn terminated() -> Option<i32> {
+    match TERMINATED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+#[cfg(unix)]
+fn signals() -> io::Result<()> {
+    use signal_hook::consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGTSTP, SIGCONT, SIGTERM, SIGHUP, SIGINT])?;
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            match signal {
+                SIGTSTP => {
+                    let _ = restore();
+                    let _ = signal_hook::low_level::emulate_default_handler(SIGTSTP);
+                }
+                SIGCONT => {
+                    let _ = setup();
+                }
+                signal => TERMINATED.store(signal, Ordering::SeqCst),
+            }
+        }
+    });
+    Ok(())
+}
@@ -12,12 +12,12 @@ pub struct Background {
 }
 
 impl Background {
-    pub fn new(cols: u16, rows: u16) -> Option<Self> {
-        let mut rng = rand::thread_rng();
-        let chars = vec!['*', '+'];
-        chars.choose(&mut rng).map(|x| Background {
+    pub fn new<R: Rng>(cols: u16, rows: u16, rng: &mut R) -> Option<Self> {
+        let chars = ['*', '+'];
+        chars.choose(rng).map(|x| Background {
             col: cols,
-            row: rng.gen_range(0..rows - 10),
+            // the sky ends a few rows above the ground
+            row: rng.gen_range(0..rows.saturating_sub(10).max(1)),
             chr: *x,
         })
     }
@@ -0,0 +1,268 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use rand::rngs::StdRng;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+use super::ObstacleGenerator;
+use crate::config::Level;
+
+// what comes up at one column of the ground
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Tile {
+    Ground,
+    Crater,
+    Monster,
+    JumpingMonster,
+}
+
+impl Tile {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(Tile::Ground),
+            '_' => Some(Tile::Crater),
+            'o' => Some(Tile::Monster),
+            'O' => Some(Tile::JumpingMonster),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Obstacle {
+    // the distance from the start of the course in columns
+    pub at: usize,
+    pub kind: Tile,
+    // the number of columns in a row, e.g. for wider craters
+    #[serde(default = "one")]
+    pub width: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+// a course is written as a strip like `..._..__...o..O...`, one character per
+// column, or as a list of obstacles at their distances
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Spec {
+    Strip(String),
+    Obstacles(Vec<Obstacle>),
+}
+
+// unlike an untagged enum this keeps the error of a broken obstacle
+impl<'de> Deserialize<'de> for Spec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpecVisitor;
+
+        impl<'de> Visitor<'de> for SpecVisitor {
+            type Value = Spec;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a strip like \"..._..o..\" or a list of obstacles")
+            }
+
+            fn visit_str<E: de::Error>(self, strip: &str) -> Result<Spec, E> {
+                Ok(Spec::Strip(strip.to_string()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Spec, A::Error> {
+                Vec::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(Spec::Obstacles)
+            }
+        }
+
+        deserializer.deserialize_any(SpecVisitor)
+    }
+}
+
+// the most columns a course can have, so a typo in a distance does not take
+// all the memory
+const MAX_LEN: usize = 10_000;
+
+// a hand-made level, the world brings up exactly these tiles one after the
+// other; at the end the course starts over
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(try_from = "Spec", into = "Spec")]
+pub struct Course {
+    spec: Spec,
+    tiles: Vec<Tile>,
+    // how far the course got, a level starts with a fresh copy
+    position: usize,
+}
+
+impl ObstacleGenerator for Course {
+    fn next(&mut self, _: &Level, _: &mut StdRng) -> Tile {
+        let tile = self.tiles[self.position % self.tiles.len()];
+        self.position += 1;
+        tile
+    }
+}
+
+impl TryFrom<Spec> for Course {
+    type Error = String;
+
+    fn try_from(spec: Spec) -> Result<Self, Self::Error> {
+        let tiles = match &spec {
+            // whitespace is left out, so a long strip can span several lines
+            Spec::Strip(strip) => strip
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| {
+                    Tile::from_char(c).ok_or_else(|| {
+                        format!(
+                            "`{}` is not part of a course, it is made of `.` for the ground, \
+                             `_` for a crater, `o` for a monster and `O` for a jumping monster",
+                            c
+                        )
+                    })
+                })
+                .collect::<Result<Vec<Tile>, String>>()?,
+            Spec::Obstacles(obstacles) => {
+                let mut len = 0;
+                for obstacle in obstacles {
+                    if obstacle.kind == Tile::Ground {
+                        return Err(format!(
+                            "the obstacle at {} is just ground, leave it out",
+                            obstacle.at
+                        ));
+                    }
+                    if obstacle.width == 0 {
+                        return Err(format!(
+                            "the obstacle at {} has to be at least 1 wide",
+                            obstacle.at
+                        ));
+                    }
+                    len = match obstacle.at.checked_add(obstacle.width) {
+                        Some(end) if end <= MAX_LEN => len.max(end),
+                        _ => {
+                            return Err(format!(
+                            "the obstacle at {} ends after the course, which is at most {} long",
+                            obstacle.at, MAX_LEN
+                        ))
+                        }
+                    };
+                }
+                let mut tiles = vec![Tile::Ground; len];
+                for obstacle in obstacles {
+                    tiles[obstacle.at..obstacle.at + obstacle.width].fill(obstacle.kind);
+                }
+                tiles
+            }
+        };
+        if tiles.is_empty() {
+            return Err("the course is empty".to_string());
+        }
+        if tiles.len() > MAX_LEN {
+            return Err(format!(
+                "the course is {} long, it can be at most {}",
+                tiles.len(),
+                MAX_LEN
+            ));
+        }
+        Ok(Course {
+            spec,
+            tiles,
+            position: 0,
+        })
+    }
+}
+
+impl From<Course> for Spec {
+    fn from(course: Course) -> Spec {
+        course.spec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use Tile::*;
+
+    #[derive(Deserialize, Debug)]
+    struct Level {
+        course: Course,
+    }
+
+    fn parse(course: &str) -> Result<Vec<Tile>, toml::de::Error> {
+        toml::from_str::<Level>(&format!("course = {}", course)).map(|level| level.course.tiles)
+    }
+
+    #[test]
+    fn parses_a_strip() {
+        assert_eq!(
+            parse(r#""._ o O""#).unwrap(),
+            vec![Ground, Crater, Monster, JumpingMonster]
+        );
+        assert_eq!(
+            parse("\"\"\"\n..__\n..o\n\"\"\"").unwrap(),
+            vec![Ground, Ground, Crater, Crater, Ground, Ground, Monster]
+        );
+        let error = parse(r#""..x..""#).unwrap_err();
+        assert!(error.message().contains("`x` is not part of a course"));
+        assert!(parse(r#"" ""#).is_err());
+    }
+
+    #[test]
+    fn parses_a_list_of_obstacles() {
+        let tiles = parse(
+            r#"[{ at = 2, kind = "crater", width = 2 }, { at = 5, kind = "jumping_monster" }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tiles,
+            vec![Ground, Ground, Crater, Crater, Ground, JumpingMonster]
+        );
+        let error = parse(r#"[{ at = 1, kind = "rock" }]"#).unwrap_err();
+        assert!(error.message().contains("rock"));
+    }
+
+    #[test]
+    fn rejects_broken_obstacles() {
+        for (course, message) in [
+            (r#"[{ at = 1, kind = "ground" }]"#, "just ground"),
+            (
+                r#"[{ at = 1, kind = "crater", width = 0 }]"#,
+                "at least 1 wide",
+            ),
+            (
+                r#"[{ at = 9999, kind = "crater", width = 2 }]"#,
+                "at most 10000",
+            ),
+            (
+                r#"[{ at = 1, kind = "monster", width = -1 }]"#,
+                "expected usize",
+            ),
+        ] {
+            let error = parse(course).unwrap_err();
+            assert!(error.message().contains(message), "{}", error.message());
+        }
+        let strip = format!("\"{}\"", ".".repeat(MAX_LEN + 1));
+        assert!(parse(&strip).is_err());
+    }
+
+    #[test]
+    fn reports_the_line_of_a_broken_course() {
+        let content =
+            "[[levels]]\npoints = 5\n\n[[levels]]\ncourse = [{ at = 1, kind = \"ground\" }]\n";
+        let error = toml::from_str::<Config>(content).unwrap_err();
+        let line = error
+            .span()
+            .map(|span| crate::config::line(content, span.start));
+        assert_eq!(line, Some(5));
+    }
+
+    #[test]
+    fn starts_over_at_the_end() {
+        let mut course = Course::try_from(Spec::Strip("._".to_string())).unwrap();
+        let level = crate::config::Level::default();
+        let mut rng = rand::SeedableRng::seed_from_u64(1);
+        let tiles: Vec<Tile> = (0..5).map(|_| course.next(&level, &mut rng)).collect();
+        assert_eq!(tiles, vec![Ground, Crater, Ground, Crater, Ground]);
+    }
+}
@@ -0,0 +1,252 @@
+// SPDX-FileCopyrightText: 2023 Birger Schacht <birger@rantanplan.org>
+//
+// SPDX-License-Identifier: MIT
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::collections::VecDeque;
+
+use super::{Monster, Rover, Tile};
+use crate::config::Level;
+
+// decides what comes up at the right edge of the world, one column of the
+// ground after the other
+pub trait ObstacleGenerator {
+    fn next(&mut self, level: &Level, rng: &mut StdRng) -> Tile;
+
+    // a tile that came up from somewhere else, e.g. the course of a level,
+    // which the next obstacles have to fit to
+    fn record(&mut self, _tile: Tile) {}
+}
+
+// the columns of ground that are left free after every obstacle
+const GAP: usize = 10;
+
+// rolls the obstacles with the probabilities of the level, but only lets
+// through the ones the rover can get past
+pub struct Passable {
+    // the tiles that came up last, the latest one at the end
+    history: VecDeque<Tile>,
+    // the rest of an obstacle that is wider than one column
+    queue: VecDeque<Tile>,
+    // how high the rover is on every tick of a jump
+    arc: Vec<u16>,
+}
+
+impl Default for Passable {
+    fn default() -> Self {
+        Passable {
+            history: VecDeque::new(),
+            queue: VecDeque::new(),
+            arc: Rover::arc(),
+        }
+    }
+}
+
+impl ObstacleGenerator for Passable {
+    fn next(&mut self, level: &Level, rng: &mut StdRng) -> Tile {
+        let tile = match self.queue.pop_front() {
+            Some(tile) => tile,
+            None => self.roll(level, rng),
+        };
+        self.push(tile);
+        tile
+    }
+
+    fn record(&mut self, tile: Tile) {
+        // the rest of a rolled obstacle would land in the middle of the
+        // other ones
+        self.queue.clear();
+        self.push(tile);
+    }
+}
+
+impl Passable {
+    fn push(&mut self, tile: Tile) {
+        self.history.push_back(tile);
+        // no rule looks further back than a whole jump and the gap
+        while self.history.len() > self.arc.len() + usize::from(Rover::RANGE) + GAP {
+            self.history.pop_front();
+        }
+    }
+
+    fn roll(&mut self, level: &Level, rng: &mut StdRng) -> Tile {
+        let free = self
+            .history
+            .iter()
+            .rev()
+            .take(GAP)
+            .all(|tile| *tile == Tile::Ground);
+        if !free || !rng.gen_bool(0.5) {
+            return Tile::Ground;
+        }
+        let obstacle = if rng.gen_bool(level.prob_crater_one) {
+            vec![Tile::Crater]
+        } else if rng.gen_bool(level.prob_crater_two) {
+            vec![Tile::Crater; 2]
+        } else if rng.gen_bool(level.prob_crater_three) {
+            vec![Tile::Crater; 3]
+        } else if rng.gen_bool(level.prob_monster) {
+            vec![Tile::Monster]
+        } else if rng.gen_bool(level.prob_monster_jumping) {
+            vec![Tile::JumpingMonster]
+        } else {
+            return Tile::Ground;
+        };
+        if !self.passable(&obstacle) {
+            return Tile::Ground;
+        }
+        self.queue.extend(&obstacle[1..]);
+        obstacle[0]
+    }
+
+    // the rover has to be in the air while a crater passes under it, so a
+    // row of craters must not be wider than the ground a jump gets across;
+    // monsters can not be jumped over but have to be shot, which works from
+    // the ground for a walking monster, while a jumping one is only hit by a
+    // bullet fired at its height, so the rover needs room for a whole jump
+    // and has to get as high as the monster
+    fn passable(&self, obstacle: &[Tile]) -> bool {
+        let range = usize::from(Rover::RANGE);
+        let jump = self.arc.len() + 1 - range;
+        let heights = || std::iter::once(&0).chain(&self.arc);
+        let reachable = Monster::hop()
+            .iter()
+            .all(|height| heights().any(|h| h == height));
+        let tiles: Vec<Tile> = self.history.iter().chain(obstacle).copied().collect();
+        let craters_before = |index: usize, columns: usize| {
+            tiles[index.saturating_sub(columns)..index].contains(&Tile::Crater)
+        };
+
+        let start = self.history.len();
+        for (index, tile) in tiles.iter().enumerate().skip(start) {
+            match tile {
+                Tile::Crater => {
+                    let width = tiles[..=index]
+                        .iter()
+                        .rev()
+                        .take_while(|tile| **tile == Tile::Crater)
+                        .count();
+                    if width > jump {
+                        return false;
+                    }
+                }
+                Tile::Monster if craters_before(index, range) => return false,
+                Tile::JumpingMonster
+                    if !reachable || craters_before(index, self.arc.len() + range) =>
+                {
+                    return false
+                }
+                _ => (),
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn level(prob_crater_one: f64, prob_monster_jumping: f64) -> Level {
+        Level {
+            prob_crater_one,
+            prob_crater_two: 0.0,
+            prob_crater_three: 0.0,
+            prob_monster: 0.0,
+            prob_monster_jumping,
+            ..Default::default()
+        }
+    }
+
+    // the distance of every jumping monster to the crater before it
+    fn distances(tiles: &[Tile]) -> Vec<usize> {
+        let mut crater = None;
+        let mut distances = vec![];
+        for (index, tile) in tiles.iter().enumerate() {
+            match tile {
+                Tile::Crater => crater = Some(index),
+                Tile::JumpingMonster => distances.extend(crater.map(|crater| index - crater)),
+                _ => (),
+            }
+        }
+        distances
+    }
+
+    #[test]
+    fn jumping_monsters_need_room_after_a_crater() {
+        let room = Rover::arc().len() + usize::from(Rover::RANGE);
+        let mut passable = Passable::default();
+        passable.record(Tile::Crater);
+        for _ in 0..room - 1 {
+            passable.record(Tile::Ground);
+        }
+        assert!(!passable.passable(&[Tile::JumpingMonster]));
+        assert!(passable.passable(&[Tile::Monster]));
+        passable.record(Tile::Ground);
+        assert!(passable.passable(&[Tile::JumpingMonster]));
+    }
+
+    #[test]
+    fn keeps_the_craters_of_the_last_level() {
+        let room = Rover::arc().len() + usize::from(Rover::RANGE);
+        let craters = level(1.0, 0.0);
+        let monsters = level(0.0, 1.0);
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut passable = Passable::default();
+            let mut tiles = vec![];
+            while tiles.last() != Some(&Tile::Crater) {
+                tiles.push(passable.next(&craters, &mut rng));
+            }
+            // the level changes right after the crater
+            for _ in 0..200 {
+                tiles.push(passable.next(&monsters, &mut rng));
+            }
+            let distances = distances(&tiles);
+            assert!(!distances.is_empty());
+            assert!(distances.iter().all(|distance| *distance >= room));
+        }
+    }
+
+    #[test]
+    fn the_world_keeps_the_generator_over_levels() {
+        let room = Rover::arc().len() + usize::from(Rover::RANGE);
+        let levels = [level(1.0, 0.0), level(0.0, 1.0)];
+        let (cols, rows) = (200, 30);
+        let mut checked = 0;
+        for seed in 0..20 {
+            let mut world = crate::world::World::new(seed);
+            while world.craters.iter().all(|crater| crater.col != cols) {
+                world.step(cols, rows, &[], &levels, 0);
+            }
+            // right after the crater came up the level changes
+            for _ in 0..room * 2 {
+                world.step(cols, rows, &[], &levels, 1);
+                if world.monsters.iter().any(|monster| monster.col == cols) {
+                    let crater = world.craters.iter().map(|crater| crater.col).max();
+                    assert!(crater.unwrap() <= cols - room as u16);
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn fits_to_the_tiles_of_a_course() {
+        let room = Rover::arc().len() + usize::from(Rover::RANGE);
+        let monsters = level(0.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut passable = Passable::default();
+        let mut tiles = vec![Tile::Ground, Tile::Crater];
+        for tile in &tiles {
+            passable.record(*tile);
+        }
+        for _ in 0..100 {
+            tiles.push(passable.next(&monsters, &mut rng));
+        }
+        assert!(distances(&tiles)[0] >= room);
+    }
+}
@@ -2,22 +2,37 @@
 //
 // SPDX-License-Identifier: MIT
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 mod background;
 mod bullet;
+mod course;
 mod crater;
+mod generator;
 mod monster;
 mod rover;
 
 use crate::config;
-use background::Background;
-use bullet::Bullet;
-use crater::Crater;
-use monster::Monster;
-use rover::Rover;
+use crate::context::Action;
+pub use background::Background;
+pub use bullet::Bullet;
+pub use course::{Course, Obstacle, Tile};
+pub use crater::Crater;
+pub use generator::{ObstacleGenerator, Passable};
+pub use monster::Monster;
+pub use rover::Rover;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    CraterCleared,
+    Crashed,
+    MonsterCrashed,
+    MonsterKilled,
+    LevelUp,
+    Rebooted,
+}
 
-#[derive(Default)]
 pub struct World {
     pub cols: u16,
     pub rows: u16,
@@ -26,18 +41,148 @@ pub struct World {
     pub monsters: Vec<Monster>,
     pub craters: Vec<Crater>,
     pub backgrounds: Vec<Background>,
+    pub seed: u64,
+    pub rng: StdRng,
+    pub frame: u64,
+    // the level of the last step and its course, if it has one; the other
+    // levels roll their obstacles with the generator, which is kept over all
+    // levels so it knows the obstacles that are still coming up
+    pub level: Option<usize>,
+    pub course: Option<Course>,
+    pub generator: Box<dyn ObstacleGenerator>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new(rand::random())
+    }
 }
 
 impl World {
+    // the smallest terminal the game can be played in
+    pub const MIN_COLS: u16 = 40;
+    pub const MIN_ROWS: u16 = 20;
+
+    pub fn new(seed: u64) -> Self {
+        World {
+            cols: 0,
+            rows: 0,
+            rover: Rover::default(),
+            bullets: vec![],
+            monsters: vec![],
+            craters: vec![],
+            backgrounds: vec![],
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            frame: 0,
+            level: None,
+            course: None,
+            generator: Box::new(Passable::default()),
+        }
+    }
+
+    // moves the obstacles along with the ground when the terminal changes its
+    // size and drops everything that does not fit anymore
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        if (cols, rows) == (self.cols, self.rows) {
+            return;
+        }
+        if self.rows > 0 {
+            let shift = |row: u16| (row + rows).saturating_sub(self.rows);
+            self.monsters
+                .iter_mut()
+                .for_each(|monster| monster.row = shift(monster.row));
+            self.bullets
+                .iter_mut()
+                .for_each(|bullet| bullet.row = shift(bullet.row));
+        }
+        self.craters.retain(|crater| crater.col < cols);
+        self.monsters.retain(|monster| monster.col < cols);
+        self.bullets.retain(|bullet| bullet.col < cols);
+        self.backgrounds
+            .retain(|background| background.col < cols && background.row < rows.saturating_sub(10));
+        self.rover.update(20, rows.saturating_sub(4));
+        self.cols = cols;
+        self.rows = rows;
+    }
+
     pub fn shoot(&mut self) {
         self.bullets
             .push(Bullet::new(self.rover.col + 6, self.rover.row()));
     }
 
-    pub fn update(&mut self, cols: u16, rows: u16, level: &config::Level) {
-        self.cols = cols;
-        self.rows = rows;
-        self.rover.update(20, rows - 4);
+    // one full tick of the simulation: moves and spawns the obstacles, applies
+    // the actions, checks for collisions and reports what happened
+    pub fn step(
+        &mut self,
+        cols: u16,
+        rows: u16,
+        actions: &[Action],
+        levels: &[config::Level],
+        level: usize,
+    ) -> Vec<Event> {
+        if self.level != Some(level) {
+            self.level = Some(level);
+            self.course = levels[level].course.clone();
+        }
+        let mut events = self.update(cols, rows, &levels[level]);
+
+        for action in actions {
+            match action {
+                Action::Jump => self.rover.jump(),
+                Action::Shoot => self.shoot(),
+                Action::Quit | Action::Pause => (),
+            }
+        }
+
+        if self.rover.moving() {
+            if self
+                .craters
+                .iter()
+                .any(|crater| crater.col == self.rover.col)
+            {
+                self.rover.points += 4;
+                events.push(Event::CraterCleared);
+            }
+            // the rover only crashes once per tick, even if a crater and a
+            // monster reach it together
+            if self
+                .craters
+                .iter()
+                .any(|crater| self.rover.range().contains(&crater.col))
+                && !self.rover.jumping()
+            {
+                self.rover.crash();
+                events.push(Event::Crashed);
+            } else if self
+                .monsters
+                .iter()
+                .any(|monster| monster.col == self.rover.col + 5)
+            {
+                self.rover.monstercrash();
+                events.push(Event::MonsterCrashed);
+            }
+        }
+        self.rover.tick();
+
+        // sum up the points of all levels up to now...
+        let points: u16 = levels[0..=level].iter().map(|x| x.points).sum();
+        if points <= self.rover.points {
+            events.push(Event::LevelUp);
+        }
+
+        if self.rover.rebooting() {
+            self.reset();
+            events.push(Event::Rebooted);
+        }
+
+        self.frame += 1;
+        events
+    }
+
+    fn update(&mut self, cols: u16, rows: u16, level: &config::Level) -> Vec<Event> {
+        let mut events = vec![];
+        self.resize(cols, rows);
         self.bullets.iter_mut().for_each(|bullet| bullet.col += 1);
 
         let mut bulletremovals: Vec<u16> = vec![];
@@ -48,10 +193,12 @@ impl World {
             }) {
                 self.monsters.remove(pos);
                 bulletremovals.push(bullet.col);
+                events.push(Event::MonsterKilled);
             }
         }
+        // bullets that left the screen can not hit anything anymore
         self.bullets
-            .retain(|bullet| !bulletremovals.contains(&bullet.col));
+            .retain(|bullet| !bulletremovals.contains(&bullet.col) && bullet.col < self.cols);
 
         if self.rover.moving() {
             self.craters.iter_mut().for_each(|crater| crater.col -= 1);
@@ -61,46 +208,45 @@ impl World {
                 .for_each(|monster| monster.col -= 1);
             self.monsters.iter_mut().for_each(|monster| monster.jump());
             self.monsters.retain(|monster| monster.col > 0);
-            if self.rover.tick % 8 == 0 {
+            if self.rover.tick.is_multiple_of(8) {
                 self.backgrounds
                     .iter_mut()
                     .for_each(|background| background.col -= 1);
             }
             self.backgrounds.retain(|background| background.col > 0);
 
-            let mut rng = rand::thread_rng();
-            let range = cols - 10..cols;
-            if self
-                .monsters
-                .iter()
-                .all(|monster| !range.contains(&monster.col))
-                && self
-                    .craters
-                    .iter()
-                    .all(|crater| !range.contains(&crater.col))
-                && rng.gen_bool(0.5)
-            {
-                if rng.gen_bool(level.prob_crater_one) {
-                    self.craters.push(Crater::new(cols, 0));
-                } else if rng.gen_bool(level.prob_crater_two) {
-                    self.craters.push(Crater::new(cols, 0));
-                    self.craters.push(Crater::new(cols + 1, 0));
-                } else if rng.gen_bool(level.prob_crater_three) {
-                    self.craters.push(Crater::new(cols, 0));
-                    self.craters.push(Crater::new(cols + 1, 0));
-                    self.craters.push(Crater::new(cols + 2, 0));
-                } else if rng.gen_bool(level.prob_monster) {
-                    self.monsters.push(Monster::new(cols, rows - 4));
-                } else if rng.gen_bool(level.prob_monster_jumping) {
-                    self.monsters.push(Monster::jumping(cols, rows - 4));
+            let tile = match &mut self.course {
+                Some(course) => {
+                    let tile = course.next(level, &mut self.rng);
+                    self.generator.record(tile);
+                    tile
                 }
-            }
+                None => self.generator.next(level, &mut self.rng),
+            };
+            self.spawn(tile, cols, rows);
+
+            let rng = &mut self.rng;
             if rng.gen_bool(0.02) {
-                if let Some(x) = Background::new(cols, rows) {
+                if let Some(x) = Background::new(cols, rows, rng) {
                     self.backgrounds.push(x);
                 }
             }
         }
+        events
+    }
+
+    // brings up the next tile of the ground at the right edge
+    fn spawn(&mut self, tile: Tile, cols: u16, rows: u16) {
+        match tile {
+            Tile::Ground => (),
+            Tile::Crater => self.craters.push(Crater::new(cols, 0)),
+            Tile::Monster => self
+                .monsters
+                .push(Monster::new(cols, rows.saturating_sub(4))),
+            Tile::JumpingMonster => self
+                .monsters
+                .push(Monster::jumping(cols, rows.saturating_sub(4))),
+        }
     }
 
     pub fn reset(&mut self) {
@@ -108,3 +254,72 @@ impl World {
         self.monsters.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new(1);
+        world.resize(100, 30);
+        world.craters.push(Crater::new(80, 0));
+        world.craters.push(Crater::new(30, 0));
+        world.monsters.push(Monster::new(90, 26));
+        world.bullets.push(Bullet::new(40, 26));
+        world
+    }
+
+    #[test]
+    fn crashes_once_into_a_crater_and_a_monster() {
+        let content = "[[levels]]\ncourse = \"..........o..._......\"\n";
+        let path =
+            std::env::temp_dir().join(format!("marsrover-crash-{}.toml", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let config = config::Config::read(Some(&path), &[]);
+        let _ = std::fs::remove_file(&path);
+        let mut ctx = crate::context::Context::new(1, config.unwrap());
+        ctx.world.rover.lives = 1;
+        let mut events = vec![];
+        while ctx.run() && ctx.world.frame < 1000 {
+            events.extend(ctx.step(60, 25, &[]));
+        }
+        assert_eq!(ctx.world.rover.lives, 0);
+        let crashes = events
+            .iter()
+            .filter(|event| matches!(event, Event::Crashed | Event::MonsterCrashed))
+            .count();
+        assert_eq!(crashes, 1);
+    }
+
+    #[test]
+    fn resizing_drops_what_is_past_the_edge() {
+        let mut world = world();
+        world.resize(60, 30);
+        assert_eq!(world.craters.len(), 1);
+        assert_eq!(world.craters[0].col, 30);
+        assert!(world.monsters.is_empty());
+        assert_eq!(world.bullets.len(), 1);
+
+        // growing again does not bring anything back
+        world.resize(100, 30);
+        assert_eq!(world.craters.len(), 1);
+        assert_eq!((world.cols, world.rows), (100, 30));
+    }
+
+    #[test]
+    fn resizing_to_the_same_size_keeps_everything() {
+        let mut world = world();
+        world.resize(100, 30);
+        assert_eq!(world.craters.len(), 2);
+        assert_eq!(world.monsters.len(), 1);
+    }
+
+    #[test]
+    fn resizing_keeps_things_on_the_ground() {
+        let mut world = world();
+        world.resize(100, 25);
+        assert_eq!(world.monsters[0].row, 21);
+        assert_eq!(world.bullets[0].row, 21);
+        assert_eq!(world.rover.row(), world.rows - 4);
+    }
+}
@@ -23,6 +23,18 @@ impl Monster {
             jumping: Some(0),
         }
     }
+    // how high above the ground a jumping monster is on every tick of a hop
+    pub fn hop() -> Vec<u16> {
+        let mut monster = Monster::jumping(0, 2);
+        let mut hop = vec![];
+        loop {
+            monster.jump();
+            hop.push(2 - monster.row);
+            if monster.jumping == Some(0) {
+                return hop;
+            }
+        }
+    }
     pub fn jump(&mut self) {
         if let Some(x) = self.jumping {
             match x {
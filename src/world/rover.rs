@@ -34,6 +34,24 @@ impl Default for Rover {
 }
 
 impl Rover {
+    // the width of the rover that can fall into a crater or be hit by a monster
+    pub const RANGE: u16 = 5;
+
+    // how high above the ground the rover is on every tick of a jump
+    pub fn arc() -> Vec<u16> {
+        let mut rover = Rover {
+            row: 2,
+            ..Default::default()
+        };
+        rover.jump();
+        let mut arc = vec![];
+        while rover.jumping() {
+            arc.push(rover.row - rover.row());
+            rover.tick();
+        }
+        arc
+    }
+
     pub fn update(&mut self, col: u16, row: u16) {
         self.col = col;
         self.row = row;
@@ -86,12 +104,12 @@ impl Rover {
 
     pub fn crash(&mut self) {
         self.state = RoverState::Crash(0);
-        self.lives -= 1;
+        self.lives = self.lives.saturating_sub(1);
     }
 
     pub fn monstercrash(&mut self) {
         self.state = RoverState::Monster(0);
-        self.lives -= 1;
+        self.lives = self.lives.saturating_sub(1);
     }
 
     pub fn jumping(&self) -> bool {
@@ -112,7 +130,7 @@ impl Rover {
     }
 
     pub fn range(&self) -> std::ops::Range<u16> {
-        self.col + 5..self.col + 10
+        self.col + 5..self.col + 5 + Rover::RANGE
     }
 }
 